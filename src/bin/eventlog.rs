@@ -0,0 +1,305 @@
+//! Tool used to inspect saved events (from a save file, a journal, or --replay)
+//! when debugging replays. Text and binary event files are both supported.
+use crippled_god::core::*;
+use crippled_god::persist::*;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "eventlog", about = "Inspects Crippled God event files.")]
+enum Command {
+    /// Lists events along with their indices
+    List {
+        /// Only list events with this name, e.g. SetPlayer (may be repeated)
+        #[structopt(long = "only", number_of_values = 1)]
+        only: Vec<String>,
+
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+
+    /// Shows how game time advanced
+    Timeline {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+
+    /// Reports the first event at which two event files differ
+    Diff {
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Writes the first LEN events to a new file, e.g. to bisect a replay bug
+    Truncate {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        len: usize,
+
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+}
+
+fn main() {
+    let command = Command::from_args();
+    let result = match command {
+        Command::List { only, path } => check_names(&only)
+            .and_then(|_| load(&path))
+            .map(|events| list(&events, &only)),
+        Command::Timeline { path } => load(&path).map(|events| timeline(&events)),
+        Command::Diff { old, new } => match (load(&old), load(&new)) {
+            (Ok(old), Ok(new)) => Ok(diff(&old, &new)),
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        },
+        Command::Truncate { input, len, output } => truncate(&input, len, &output),
+    };
+
+    match result {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<ExecutedEvents, String> {
+    load_game(path).map_err(|err| format!("couldn't load {}: {}", path.display(), err))
+}
+
+fn check_names(names: &[String]) -> Result<(), String> {
+    match names
+        .iter()
+        .find(|name| !EVENT_NAMES.contains(&name.as_str()))
+    {
+        Some(name) => Err(format!(
+            "unknown event {}, expected one of {}",
+            name,
+            EVENT_NAMES.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+fn list(events: &ExecutedEvents, only: &[String]) -> Vec<String> {
+    events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| only.is_empty() || only.iter().any(|name| name == event.name()))
+        .map(|(index, event)| format!("{:>6}  {}", index, event))
+        .collect()
+}
+
+/// Returns a line for each AdvanceTime event with the time, how much time
+/// elapsed, and the number of events that executed at that time.
+fn timeline(events: &ExecutedEvents) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut last: Option<(usize, Time)> = None;
+    let mut count = 0;
+    for (index, event) in events.iter().enumerate() {
+        if let Event::AdvanceTime(time) = event {
+            if let Some(line) = timeline_line(last, count) {
+                lines.push(line);
+            }
+            let delta = last.map_or(0, |(_, prev)| time.0 - prev.0);
+            if delta < 0 {
+                lines.push(format!("{:>6}  time went backwards", index));
+            }
+            last = Some((index, *time));
+            count = 0;
+        } else {
+            count += 1;
+        }
+    }
+    if let Some(line) = timeline_line(last, count) {
+        lines.push(line);
+    }
+    lines
+}
+
+fn timeline_line(entry: Option<(usize, Time)>, count: usize) -> Option<String> {
+    entry.map(|(index, time)| format!("{:>6}  {:>8}  {} events", index, time.to_string(), count))
+}
+
+fn diff(old: &ExecutedEvents, new: &ExecutedEvents) -> Vec<String> {
+    // Time gives a bit of context which is handy when reading the logs.
+    let mut time = None;
+    for (index, (old_event, new_event)) in old.iter().zip(new.iter()).enumerate() {
+        if old_event != new_event {
+            return vec![
+                format!("first difference at event {}{}", index, time_suffix(time)),
+                format!("   old: {}", old_event),
+                format!("   new: {}", new_event),
+            ];
+        }
+        if let Event::AdvanceTime(t) = old_event {
+            time = Some(*t);
+        }
+    }
+
+    let (longer, name) = if old.len() > new.len() {
+        (old, "old")
+    } else {
+        (new, "new")
+    };
+    if old.len() == new.len() {
+        vec![format!("identical ({} events)", old.len())]
+    } else {
+        let index = old.len().min(new.len());
+        let event = longer.iter().nth(index).unwrap();
+        vec![
+            format!(
+                "{} has {} extra events starting at event {}{}",
+                name,
+                longer.len() - index,
+                index,
+                time_suffix(time)
+            ),
+            format!("   {}: {}", name, event),
+        ]
+    }
+}
+
+fn time_suffix(time: Option<Time>) -> String {
+    time.map_or_else(String::new, |t| format!(" (time {})", t))
+}
+
+/// The output uses the same format as the input.
+fn truncate(input: &Path, len: usize, output: &Path) -> Result<Vec<String>, String> {
+    let events = load(input)?;
+    if len > events.len() {
+        return Err(format!(
+            "{} only has {} events",
+            input.display(),
+            events.len()
+        ));
+    }
+
+    let truncated = prefix(&events, len);
+    saved_format(input)
+        .and_then(|format| save_game(output, &truncated, format))
+        .map_err(|err| format!("couldn't write {}: {}", output.display(), err))?;
+    Ok(vec![format!(
+        "wrote {} of {} events to {}",
+        len,
+        events.len(),
+        output.display()
+    )])
+}
+
+fn prefix(events: &ExecutedEvents, len: usize) -> ExecutedEvents {
+    let mut result = ExecutedEvents::new();
+    for event in events.iter().take(len) {
+        result.append(event);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> ExecutedEvents {
+        let mut events = ExecutedEvents::new();
+        events.append(&Event::NewGame(1));
+        events.append(&Event::NewBranch);
+        events.append(&Event::AdvanceTime(Time(0)));
+        events.append(&Event::SetPlayer(Point::new(1, 2)));
+        events.append(&Event::SetPlayerReady(Time(10)));
+        events.append(&Event::AdvanceTime(Time(10)));
+        events.append(&Event::SetPlayer(Point::new(2, 2)));
+        events
+    }
+
+    #[test]
+    fn test_list() {
+        let lines = list(&events(), &[]);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[3], "     3  SetPlayer((1, 2))");
+
+        let only = vec!["SetPlayer".to_string(), "NewGame".to_string()];
+        let lines = list(&events(), &only);
+        assert_eq!(
+            lines,
+            vec![
+                "     0  NewGame(1)",
+                "     3  SetPlayer((1, 2))",
+                "     6  SetPlayer((2, 2))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_names() {
+        assert!(check_names(&[]).is_ok());
+        assert!(check_names(&["SetPlayer".to_string(), "NewNPC".to_string()]).is_ok());
+
+        let err = check_names(&["SetPlayer".to_string(), "Moved".to_string()]).unwrap_err();
+        assert!(
+            err.starts_with("unknown event Moved, expected one of AddMessage, "),
+            "{}",
+            err
+        );
+        assert!(err.ends_with(", SetTerrain"), "{}", err);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let lines = timeline(&events());
+        assert_eq!(
+            lines,
+            vec!["     2      0.0s  2 events", "     5      1.0s  1 events"]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = events();
+        assert_eq!(diff(&old, &events()), vec!["identical (7 events)"]);
+
+        let mut new = prefix(&old, 6);
+        new.append(&Event::SetPlayer(Point::new(3, 3)));
+        let lines = diff(&old, &new);
+        assert_eq!(lines[0], "first difference at event 6 (time 1.0s)");
+        assert_eq!(lines[1], "   old: SetPlayer((2, 2))");
+        assert_eq!(lines[2], "   new: SetPlayer((3, 3))");
+
+        new.append(&Event::NewLevel);
+        let lines = diff(&new, &prefix(&new, 7));
+        assert_eq!(
+            lines[0],
+            "old has 1 extra events starting at event 7 (time 1.0s)"
+        );
+        assert_eq!(lines[1], "   old: NewLevel");
+    }
+
+    #[test]
+    fn test_truncate() {
+        let input = PathBuf::from("/tmp/crippled-god-eventlog.bin");
+        let output = PathBuf::from("/tmp/crippled-god-eventlog-truncated.bin");
+        save_game(&input, &events(), EventFormat::Binary).unwrap();
+
+        truncate(&input, 3, &output).unwrap();
+        assert_eq!(saved_format(&output).unwrap(), EventFormat::Binary);
+        let truncated = load(&output).unwrap();
+        assert_eq!(
+            truncated.iter().cloned().collect::<Vec<_>>(),
+            vec![
+                Event::NewGame(1),
+                Event::NewBranch,
+                Event::AdvanceTime(Time(0))
+            ]
+        );
+
+        assert!(truncate(&input, 8, &output).is_err());
+    }
+}
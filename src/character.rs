@@ -1,28 +1,119 @@
 use super::core::*;
 use super::level::*;
+use super::species::*;
 // use super::player::*;
 use rand::rngs::SmallRng;
 use rand::seq::SliceRandom;
 
-pub fn can_move_to(store: &Store, loc: Point) -> bool {
-	// level.is_valid(loc) && compatible_terrain(level, loc) && has_no_char(level, loc)
-	is_level_loc(store, loc) && compatible_terrain(store, loc)
+/// How long it takes a species with move_scale 1.0 to move orthogonally over ground.
+const BASE_MOVEMENT_DELAY: f32 = 2.0;
+
+/// Diagonal moves cover more distance and take correspondingly longer.
+const DIAGONAL_SCALING: f32 = 1.4;
+
+pub fn can_move_to(store: &Store, species: &Species, loc: Point) -> bool {
+	is_level_loc(store, loc)
+		&& movement_delay(species, get_level_terrain(store, loc)).is_some()
+		&& has_no_char(store, loc)
+}
+
+pub fn has_no_char(store: &Store, loc: Point) -> bool {
+	store.lookup_ref(&cell(loc), Predicate::Character).is_none()
+}
+
+/// Returns how long it takes the species to move orthogonally onto the terrain or
+/// None if the species cannot move there.
+pub fn movement_delay(species: &Species, terrain: Terrain) -> Option<f32> {
+	let flies_or_swims = species.has_flag(Flag::Airborne) || species.has_flag(Flag::Aquatic);
+	let scaling = match terrain {
+		Terrain::DeepWater if flies_or_swims => 1.0,
+		Terrain::DeepWater => return None,
+		Terrain::Ground => 1.0,
+		Terrain::ShallowWater if flies_or_swims => 1.0,
+		Terrain::ShallowWater => 1.5,
+		Terrain::Wall => return None, // TODO: add support for status effects
+	};
+	Some(scaling * species.move_scale * BASE_MOVEMENT_DELAY)
+}
+
+/// Moves a character to a new location (or places it for the first time) and
+/// updates the back reference from the cell to the character.
+pub fn set_char_loc(store: &mut Store, event: &Event, name: &Subject, loc: Point) {
+	let new_cell = cell(loc);
+	if let Some(occupant) = store.lookup_ref(&new_cell, Predicate::Character) {
+		assert!(
+			occupant == *name,
+			"{} can't move to {} because {} is already there",
+			name,
+			loc,
+			occupant
+		);
+	}
+
+	if let Some(old_loc) = store.lookup_pt(name, Predicate::Loc) {
+		let old_cell = cell(old_loc);
+		if store.lookup_ref(&old_cell, Predicate::Character).as_ref() == Some(name) {
+			store.remove(event, &old_cell, Predicate::Character);
+		}
+	}
+	store.insert(event, name, Predicate::Loc, Object::Point(loc));
+	store.insert(
+		event,
+		&new_cell,
+		Predicate::Character,
+		Object::Ref(name.clone()),
+	);
+}
+
+/// Like set_char_loc except that the move is dropped if another character got to
+/// loc first (e.g. an NPC and the player both moved there on the same tick).
+/// Returns true if the character moved.
+pub fn move_char_to(store: &mut Store, event: &Event, name: &Subject, loc: Point) -> bool {
+	match store.lookup_ref(&cell(loc), Predicate::Character) {
+		Some(occupant) if occupant != *name => false,
+		_ => {
+			set_char_loc(store, event, name, loc);
+			true
+		}
+	}
+}
+
+/// Removes a character from the level, e.g. when it dies.
+pub fn remove_char(store: &mut Store, event: &Event, name: &Subject) {
+	if let Some(loc) = store.lookup_pt(name, Predicate::Loc) {
+		let old_cell = cell(loc);
+		if store.lookup_ref(&old_cell, Predicate::Character).as_ref() == Some(name) {
+			store.remove(event, &old_cell, Predicate::Character);
+		}
+		store.remove(event, name, Predicate::Loc);
+	}
 }
 
-fn compatible_terrain(store: &Store, loc: Point) -> bool {
-	match get_level_terrain(store, loc) {
-		Terrain::DeepWater => {
-			// let ch = level.character_components.get(&entity).unwrap();
-			// ch.flags.has(CharacterFlags::Airborne) || ch.flags.has(CharacterFlags::Aquatic)
-			false
+/// Panics if the cell back references are out of sync with the character
+/// locations, e.g. if two characters are in the same cell.
+pub fn check_occupancy(store: &Store) {
+	let npcs = store.iter_by_instance_class("npc");
+	for name in std::iter::once(&*PLAYER).chain(npcs) {
+		if let Some(loc) = store.lookup_pt(name, Predicate::Loc) {
+			let occupant = store.lookup_ref(&cell(loc), Predicate::Character);
+			assert!(
+				occupant.as_ref() == Some(name),
+				"{} is at {} but the cell has {:?}",
+				name,
+				loc,
+				occupant
+			);
 		}
-		Terrain::Ground => true,
-		Terrain::ShallowWater => true,
-		Terrain::Wall => false, // TODO: add support for status effects
 	}
 }
 
-pub fn move_char_by(store: &Store, name: &Subject, dx: i32, dy: i32) -> Option<(Duration, Point)> {
+pub fn move_char_by(
+	store: &Store,
+	species: &Species,
+	name: &Subject,
+	dx: i32,
+	dy: i32,
+) -> Option<(Duration, Point)> {
 	assert!(dx != 0 || dy != 0);
 
 	let old_loc = store.lookup_pt(name, Predicate::Loc).unwrap();
@@ -31,11 +122,12 @@ pub fn move_char_by(store: &Store, name: &Subject, dx: i32, dy: i32) -> Option<(
 		x: old_loc.x + dx,
 		y: old_loc.y + dy,
 	};
-	if can_move_to(store, new_loc) {
+	if can_move_to(store, species, new_loc) {
+		let delay = movement_delay(species, get_level_terrain(store, new_loc)).unwrap();
 		if dx != 0 && dy != 0 {
-			Some((Duration::from_secs(1.4 * 2.0), new_loc))
+			Some((Duration::from_secs(DIAGONAL_SCALING * delay), new_loc))
 		} else {
-			Some((Duration::from_secs(2.0), new_loc))
+			Some((Duration::from_secs(delay), new_loc))
 		}
 	} else {
 		None
@@ -53,10 +145,134 @@ pub fn find_char_loc(store: &Store, rng: &mut SmallRng) -> Option<Point> {
 		let loc = Point::new(x, y);
 		let subject = cell(loc);
 		if let Some(Terrain::Ground) = store.lookup_terrain(&subject, Predicate::Terrain) {
-			if store.lookup_ref(&subject, Predicate::Character).is_none() {
+			if has_no_char(store, loc) {
 				return Some(loc);
 			}
 		}
 	}
 	None
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn new_store() -> Store {
+		let logger = slog::Logger::root(slog::Discard, o!());
+		let mut store = Store::new(&logger);
+		let event = Event::ResetLevel("test".to_string(), Size::new(4, 4), Terrain::Ground);
+		store.insert(
+			&event,
+			&LEVEL,
+			Predicate::Size,
+			Object::Size(Size::new(4, 4)),
+		);
+		for y in 0..4 {
+			for x in 0..4 {
+				let terrain = Object::Terrain(Terrain::Ground);
+				store.insert(&event, &cell(Point::new(x, y)), Predicate::Terrain, terrain);
+			}
+		}
+		store
+	}
+
+	#[test]
+	fn test_occupied() {
+		let table = test_species();
+		let human = table.get("human").unwrap();
+		let mut store = new_store();
+		let loc = Point::new(1, 1);
+		let event = Event::SetPlayer(loc);
+		assert!(can_move_to(&store, human, loc));
+
+		set_char_loc(&mut store, &event, &PLAYER, loc);
+		assert!(!has_no_char(&store, loc));
+		assert!(!can_move_to(&store, human, loc));
+		check_occupancy(&store);
+
+		let event = Event::SetPlayer(Point::new(2, 1));
+		set_char_loc(&mut store, &event, &PLAYER, Point::new(2, 1));
+		assert!(can_move_to(&store, human, loc));
+		assert_eq!(
+			move_char_by(&store, human, &PLAYER, 1, 0),
+			Some((Duration::from_secs(2.0), Point::new(3, 1)))
+		);
+		check_occupancy(&store);
+	}
+
+	#[test]
+	fn test_movement_delay() {
+		let text = "
+species walker
+	symbol w
+	color White
+	hps 10
+	behavior Passive
+species flier
+	symbol f
+	color White
+	hps 10
+	move_scale 0.5
+	flags Airborne
+	behavior Passive
+species crawler
+	symbol c
+	color White
+	hps 10
+	move_scale 0.7
+	behavior Passive
+";
+		let table = SpeciesTable::parse(text).unwrap();
+		let walker = table.get("walker").unwrap();
+		let flier = table.get("flier").unwrap();
+		let crawler = table.get("crawler").unwrap();
+
+		let mut store = new_store();
+		let event = Event::SetTerrain(Point::new(2, 1), Terrain::ShallowWater);
+		let terrain = Object::Terrain(Terrain::ShallowWater);
+		store.insert(&event, &cell(Point::new(2, 1)), Predicate::Terrain, terrain);
+		let terrain = Object::Terrain(Terrain::DeepWater);
+		store.insert(&event, &cell(Point::new(2, 2)), Predicate::Terrain, terrain);
+		set_char_loc(&mut store, &event, &PLAYER, Point::new(1, 1));
+
+		// Shallow water slows down walkers but not fliers (who are also faster).
+		let delay = |species, dx, dy| move_char_by(&store, species, &PLAYER, dx, dy).map(|r| r.0);
+		assert_eq!(delay(walker, 0, 1), Some(Duration(20)));
+		assert_eq!(delay(walker, 1, 0), Some(Duration(30)));
+		assert_eq!(delay(walker, 1, -1), Some(Duration(28)));
+		assert_eq!(delay(flier, 1, 0), Some(Duration(10)));
+
+		// Delays are rounded to the nearest tenth of a second.
+		assert_eq!(delay(crawler, 1, -1), Some(Duration(20)));
+		assert_eq!(delay(crawler, 1, 0), Some(Duration(21)));
+
+		// Only fliers and swimmers can enter deep water.
+		assert_eq!(delay(walker, 1, 1), None);
+		assert_eq!(delay(flier, 1, 1), Some(Duration(14)));
+		assert_eq!(movement_delay(walker, Terrain::Wall), None);
+		assert_eq!(movement_delay(flier, Terrain::Wall), None);
+	}
+
+	#[test]
+	#[should_panic(expected = "is already there")]
+	fn test_shared_cell() {
+		let mut store = new_store();
+		let loc = Point::new(1, 1);
+		let event = Event::SetPlayer(loc);
+		set_char_loc(&mut store, &event, &PLAYER, loc);
+
+		let name = Subject::new_instance(&mut store, "npc", "ay");
+		set_char_loc(&mut store, &event, &name, loc);
+	}
+
+	#[test]
+	#[should_panic(expected = "but the cell has")]
+	fn test_stale_back_ref() {
+		let mut store = new_store();
+		let loc = Point::new(1, 1);
+		let event = Event::SetPlayer(loc);
+		set_char_loc(&mut store, &event, &PLAYER, loc);
+		store.remove(&event, &cell(loc), Predicate::Character);
+		check_occupancy(&store);
+	}
+}
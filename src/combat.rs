@@ -0,0 +1,345 @@
+//! Melee combat. Characters attack adjacent characters (the player does this
+//! by moving into them) and die once their hit points reach zero.
+use super::core::*;
+use super::service::*;
+use super::species::*;
+use rand::rngs::SmallRng;
+
+/// Attacks take as long as moving orthogonally.
+pub const ATTACK_DURATION: Duration = Duration(20);
+
+/// Returns the character's current hit points.
+pub fn char_hps(store: &Store, name: &Subject) -> i32 {
+	store.lookup_int(name, Predicate::HPs).unwrap_or(0)
+}
+
+pub fn is_alive(store: &Store, name: &Subject) -> bool {
+	char_hps(store, name) > 0
+}
+
+/// Queues up an Attacked event for each of the attacker's attacks and returns
+/// how long attacking took.
+pub fn attack(
+	store: &Store,
+	species: &SpeciesTable,
+	pending: &mut PendingEvents,
+	attacker: &Subject,
+	defender: &Subject,
+) -> Duration {
+	let defender_species = species.of(store, defender);
+	let bonus = store
+		.lookup_int(attacker, Predicate::DamageBonus)
+		.unwrap_or(0);
+	for attack in species.of(store, attacker).attacks.iter() {
+		let percent = 100 - defender_species.resistance(attack.brand);
+		let damage = (attack.damage + bonus) * percent / 100;
+		pending.push_back(Event::Attacked(
+			attacker.clone(),
+			defender.clone(),
+			attack.verb.clone(),
+			attack.brand,
+			damage,
+		));
+	}
+	ATTACK_DURATION
+}
+
+/// Returns a message describing the event, e.g. "The ay bites you (10 damage).".
+fn describe_event(store: &Store, event: &Event) -> Option<String> {
+	match event {
+		Event::Attacked(attacker, defender, verb, brand, damage) => {
+			let verb = if *attacker == *PLAYER {
+				verb.clone()
+			} else {
+				format!("{}s", verb)
+			};
+			let result = match (damage, brand) {
+				(0, _) => "(no damage)".to_string(),
+				(_, Brand::Physical) => format!("({} damage)", damage),
+				(_, _) => format!("({} {} damage)", damage, brand.to_string().to_lowercase()),
+			};
+			Some(format!(
+				"{} {} {} {}.",
+				capitalize(&char_name(store, attacker)),
+				verb,
+				char_name(store, defender),
+				result
+			))
+		}
+		Event::Died(name) if *name == *PLAYER => Some("You die.".to_string()),
+		Event::Died(name) => Some(format!("{} dies.", capitalize(&char_name(store, name)))),
+		_ => None,
+	}
+}
+
+/// Applies damage and queues up Died and AddMessage events.
+pub struct CombatService;
+
+impl Service for CombatService {
+	fn on_event(
+		&mut self,
+		store: &mut Store,
+		_rng: &mut SmallRng,
+		event: &Event,
+		pending: &mut PendingEvents,
+	) {
+		match event {
+			Event::Attacked(attacker, defender, _, _, damage) => {
+				// A character can be hit multiple times before its Died event
+				// executes so ignore the blows that land after the fatal one.
+				let hps = char_hps(store, defender);
+				if hps > 0 {
+					let topic = attack_topic(attacker, defender, *damage);
+					add_message(store, event, topic, pending);
+					store.insert(event, defender, Predicate::HPs, Object::Int(hps - damage));
+					if hps - damage <= 0 {
+						pending.push_back(Event::Died(defender.clone()));
+					}
+				}
+			}
+			Event::Died(name) if *name == *PLAYER => {
+				add_message(store, event, Topic::PlayerIsDamaged, pending)
+			}
+			Event::Died(_) => add_message(store, event, Topic::NpcIsDamaged, pending),
+			_ => (),
+		}
+	}
+}
+
+fn attack_topic(attacker: &Subject, defender: &Subject, damage: i32) -> Topic {
+	match (*attacker == *PLAYER, *defender == *PLAYER, damage > 0) {
+		(true, _, true) => Topic::PlayerDidDamage,
+		(true, _, false) => Topic::PlayerDidNoDamage,
+		(_, true, true) => Topic::PlayerIsDamaged,
+		(_, true, false) => Topic::PlayerIsNotDamaged,
+		(_, _, true) => Topic::NpcIsDamaged,
+		(_, _, false) => Topic::NpcIsNotDamaged,
+	}
+}
+
+fn add_message(store: &Store, event: &Event, topic: Topic, pending: &mut PendingEvents) {
+	if let Some(text) = describe_event(store, event) {
+		pending.push_back(Event::AddMessage(topic, text));
+	}
+}
+
+fn char_name(store: &Store, name: &Subject) -> String {
+	if *name == *PLAYER {
+		"you".to_string()
+	} else {
+		let species = store.lookup_str(name, Predicate::Species).unwrap();
+		format!("the {}", species)
+	}
+}
+
+fn capitalize(text: &str) -> String {
+	let mut chars = text.chars();
+	match chars.next() {
+		Some(c) => c.to_uppercase().chain(chars).collect(),
+		None => String::new(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::character::has_no_char;
+	use super::super::level::LevelService;
+	use super::super::npc::NPCService;
+	use super::super::player::*;
+	use super::*;
+	use rand::SeedableRng;
+	use std::rc::Rc;
+
+	// Player is at (1, 1) with a bhederin to the east and an ay to the south.
+	fn new_game() -> (Store, Services, Rc<SpeciesTable>) {
+		let logger = slog::Logger::root(slog::Discard, o!());
+		let mut store = Store::new(&logger);
+		let mut services = Services::new();
+		services.register(Box::new(LevelService));
+		services.register(Box::new(CombatService));
+		let species = test_species();
+		services.register(Box::new(PlayerService::new(species.clone())));
+		services.register(Box::new(NPCService::new(species.clone())));
+
+		run(
+			&mut store,
+			&mut services,
+			vec![
+				Event::NewGame(1),
+				Event::ResetLevel("test".to_string(), Size::new(4, 4), Terrain::Ground),
+				Event::SetPlayer(Point::new(1, 1)),
+				Event::NewNPC(Point::new(2, 1), "bhederin".to_string(), 24, Time(10)),
+				Event::NewNPC(Point::new(1, 2), "ay".to_string(), 14, Time(10)),
+			],
+		);
+		(store, services, species)
+	}
+
+	// Executes the events along with any events they queued up.
+	fn run(store: &mut Store, services: &mut Services, events: Vec<Event>) -> Vec<Event> {
+		let mut rng = SmallRng::seed_from_u64(1);
+		let mut pending = PendingEvents::new();
+		for event in events {
+			pending.push_back(event);
+		}
+
+		let mut executed = Vec::new();
+		while !pending.is_empty() {
+			let event = pending.pop_front();
+			services.on_event(store, &mut rng, &event, &mut pending);
+			executed.push(event);
+		}
+		executed
+	}
+
+	fn npc_at(store: &Store, loc: Point) -> Subject {
+		store.lookup_ref(&cell(loc), Predicate::Character).unwrap()
+	}
+
+	#[test]
+	fn test_resistance() {
+		let (mut store, _, species) = new_game();
+		let bhederin = npc_at(&store, Point::new(2, 1));
+
+		let mut pending = PendingEvents::new();
+		let duration = attack(&store, &species, &mut pending, &PLAYER, &bhederin);
+		assert_eq!(duration, ATTACK_DURATION);
+		assert_eq!(
+			pending.pop_front(),
+			Event::Attacked(
+				PLAYER.clone(),
+				bhederin.clone(),
+				"hit".to_string(),
+				Brand::Physical,
+				12
+			)
+		);
+		assert!(pending.is_empty());
+
+		// Backgrounds can add to the damage the player does.
+		let event = Event::NewLevel;
+		store.insert(&event, &PLAYER, Predicate::DamageBonus, Object::Int(5));
+		attack(&store, &species, &mut pending, &PLAYER, &bhederin);
+		match pending.pop_front() {
+			Event::Attacked(_, _, _, _, damage) => assert_eq!(damage, (15 + 5) * 80 / 100),
+			event => panic!("expected Attacked but found {}", event),
+		}
+	}
+
+	#[test]
+	fn test_bump_to_attack() {
+		let (mut store, mut services, species) = new_game();
+		let bhederin = npc_at(&store, Point::new(2, 1));
+
+		let mut pending = PendingEvents::new();
+		match on_player_action(&store, &species, &mut pending, PlayerAction::DeltaEast) {
+			PlayerActionResult::Acted(duration) => assert_eq!(duration, ATTACK_DURATION),
+			_ => panic!("player should have attacked"),
+		}
+		let event = pending.pop_front();
+		run(&mut store, &mut services, vec![event]);
+		assert_eq!(char_hps(&store, &bhederin), 24 - 12);
+		assert_eq!(player_loc(&store), Point::new(1, 1));
+		assert_eq!(store.lookup_int(&bhederin, Predicate::MaxHPs), Some(24));
+	}
+
+	#[test]
+	fn test_death() {
+		let (mut store, mut services, _) = new_game();
+		let ay = npc_at(&store, Point::new(1, 2));
+
+		// The second blow lands before the ay is removed but shouldn't kill it twice.
+		let blow = Event::Attacked(
+			PLAYER.clone(),
+			ay.clone(),
+			"hit".to_string(),
+			Brand::Physical,
+			15,
+		);
+		let events = run(&mut store, &mut services, vec![blow.clone(), blow]);
+		let deaths: Vec<&Event> = events
+			.iter()
+			.filter(|event| matches!(event, Event::Died(_)))
+			.collect();
+		assert_eq!(deaths, vec![&Event::Died(ay.clone())]);
+
+		let messages: Vec<&Event> = events
+			.iter()
+			.filter(|event| matches!(event, Event::AddMessage(_, _)))
+			.collect();
+		assert_eq!(
+			messages,
+			vec![
+				&Event::AddMessage(
+					Topic::PlayerDidDamage,
+					"You hit the ay (15 damage).".to_string()
+				),
+				&Event::AddMessage(Topic::NpcIsDamaged, "The ay dies.".to_string()),
+			]
+		);
+
+		assert!(!is_alive(&store, &ay));
+		assert!(has_no_char(&store, Point::new(1, 2)));
+		assert_eq!(store.lookup_pt(&ay, Predicate::Loc), None);
+		assert_eq!(store.iter_by_instance_class("npc").count(), 1);
+		assert!(!player_is_dead(&store));
+	}
+
+	#[test]
+	fn test_player_death() {
+		let (mut store, mut services, _) = new_game();
+		let ay = npc_at(&store, Point::new(1, 2));
+
+		let mut events = Vec::new();
+		for _ in 0..8 {
+			events.push(Event::Attacked(
+				ay.clone(),
+				PLAYER.clone(),
+				"bite".to_string(),
+				Brand::Physical,
+				10,
+			));
+		}
+		run(&mut store, &mut services, events);
+		assert!(player_is_dead(&store));
+		assert_eq!(char_hps(&store, &PLAYER), -5);
+	}
+
+	#[test]
+	fn test_messages() {
+		let (store, _, _) = new_game();
+		let ay = npc_at(&store, Point::new(1, 2));
+		let describe = |event| describe_event(&store, &event).unwrap();
+
+		let event = Event::Attacked(
+			ay.clone(),
+			PLAYER.clone(),
+			"bite".to_string(),
+			Brand::Physical,
+			10,
+		);
+		assert_eq!(describe(event), "The ay bites you (10 damage).");
+
+		let event = Event::Attacked(
+			PLAYER.clone(),
+			ay.clone(),
+			"hit".to_string(),
+			Brand::Cold,
+			0,
+		);
+		assert_eq!(describe(event), "You hit the ay (no damage).");
+
+		let event = Event::Attacked(
+			PLAYER.clone(),
+			ay.clone(),
+			"hit".to_string(),
+			Brand::Fire,
+			7,
+		);
+		assert_eq!(describe(event), "You hit the ay (7 fire damage).");
+
+		assert_eq!(describe(Event::Died(ay)), "The ay dies.");
+		assert_eq!(describe(Event::Died(PLAYER.clone())), "You die.");
+		assert_eq!(describe_event(&store, &Event::NewLevel), None);
+	}
+}
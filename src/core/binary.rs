@@ -0,0 +1,260 @@
+//! Compact binary encoding used for saved events. Files start with a header:
+//!    magic        "CGEV"
+//!    format       u16, little endian (bumped when the encoding changes)
+//!    game version string (informational, e.g. for bug reports)
+//! followed by a record for each event:
+//!    length       unsigned varint, number of bytes in the payload
+//!    payload      the encoded event
+//!    checksum     u32, little endian, of the payload
+//! Integers within payloads are varints (zigzag encoded if signed) and strings
+//! are a varint length followed by UTF-8 bytes.
+use fnv::FnvHasher;
+use std::convert::TryFrom;
+use std::hash::Hasher;
+use std::io::{Error, ErrorKind, Result};
+
+pub const MAGIC: &[u8; 4] = b"CGEV";
+pub const FORMAT_VERSION: u16 = 1;
+
+pub fn has_magic(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode_header(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    put_str(buf, env!("CARGO_PKG_VERSION"));
+}
+
+/// Returns the game version that wrote the file.
+pub fn decode_header(decoder: &mut Decoder) -> Result<String> {
+    match decoder.get_bytes(MAGIC.len()) {
+        Some(magic) if magic == MAGIC => (),
+        _ => return Err(invalid_data("not a binary events file".to_string())),
+    }
+    match decoder.get_bytes(2) {
+        Some(bytes) => {
+            let version = u16::from_le_bytes([bytes[0], bytes[1]]);
+            if version != FORMAT_VERSION {
+                return Err(invalid_data(format!(
+                    "format version is {} but only {} is supported",
+                    version, FORMAT_VERSION
+                )));
+            }
+        }
+        None => return Err(invalid_data("truncated header".to_string())),
+    }
+    decoder
+        .get_str()
+        .ok_or_else(|| invalid_data("truncated header".to_string()))
+}
+
+pub fn encode_record(buf: &mut Vec<u8>, payload: &[u8]) {
+    put_uvarint(buf, payload.len() as u64);
+    buf.extend_from_slice(payload);
+    buf.extend_from_slice(&checksum(payload).to_le_bytes());
+}
+
+/// Returns None if there are no more records. A truncated record consumes the
+/// remaining bytes.
+pub fn decode_record<'a>(decoder: &mut Decoder<'a>) -> Option<Result<&'a [u8]>> {
+    if decoder.is_empty() {
+        return None;
+    }
+
+    let payload = decoder
+        .get_uvarint()
+        .and_then(|len| decoder.get_bytes(len as usize));
+    let expected = decoder.get_bytes(4);
+    match (payload, expected) {
+        (Some(payload), Some(expected)) => {
+            let expected = u32::from_le_bytes([expected[0], expected[1], expected[2], expected[3]]);
+            if checksum(payload) == expected {
+                Some(Ok(payload))
+            } else {
+                Some(Err(invalid_data("bad checksum".to_string())))
+            }
+        }
+        _ => {
+            decoder.bytes = &[];
+            Some(Err(invalid_data("truncated record".to_string())))
+        }
+    }
+}
+
+pub fn put_u8(buf: &mut Vec<u8>, value: u8) {
+    buf.push(value);
+}
+
+pub fn put_uvarint(buf: &mut Vec<u8>, value: u64) {
+    let mut value = value;
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn put_ivarint(buf: &mut Vec<u8>, value: i64) {
+    put_uvarint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+pub fn put_str(buf: &mut Vec<u8>, value: &str) {
+    put_uvarint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Used to pull values out of a byte slice. Methods return None if there
+/// are not enough bytes or the value is out of range.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get_bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count <= self.bytes.len() {
+            let (result, rest) = self.bytes.split_at(count);
+            self.bytes = rest;
+            Some(result)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_u8(&mut self) -> Option<u8> {
+        self.get_bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub fn get_uvarint(&mut self) -> Option<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.get_u8()?;
+            if shift >= 64 {
+                return None;
+            }
+            result |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn get_ivarint(&mut self) -> Option<i64> {
+        self.get_uvarint()
+            .map(|value| ((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    pub fn get_i32(&mut self) -> Option<i32> {
+        self.get_ivarint()
+            .and_then(|value| i32::try_from(value).ok())
+    }
+
+    pub fn get_str(&mut self) -> Option<String> {
+        let len = self.get_uvarint()?;
+        let bytes = self.get_bytes(len as usize)?;
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(bytes);
+    hasher.finish() as u32
+}
+
+fn invalid_data(text: String) -> Error {
+    Error::new(ErrorKind::InvalidData, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varints() {
+        let mut buf = Vec::new();
+        let values = [0, 1, -1, 63, -64, 64, 1000, -1000, i64::MAX, i64::MIN];
+        for value in values.iter() {
+            put_ivarint(&mut buf, *value);
+        }
+        put_uvarint(&mut buf, u64::MAX);
+
+        let mut decoder = Decoder::new(&buf);
+        for value in values.iter() {
+            assert_eq!(decoder.get_ivarint(), Some(*value));
+        }
+        assert_eq!(decoder.get_uvarint(), Some(u64::MAX));
+        assert!(decoder.is_empty());
+        assert_eq!(decoder.get_uvarint(), None);
+    }
+
+    #[test]
+    fn test_small_values_are_small() {
+        let mut buf = Vec::new();
+        put_ivarint(&mut buf, 50);
+        put_ivarint(&mut buf, -50);
+        assert_eq!(buf.len(), 2);
+    }
+
+    #[test]
+    fn test_i32_range() {
+        let mut buf = Vec::new();
+        put_ivarint(&mut buf, i64::from(i32::MAX) + 1);
+        assert_eq!(Decoder::new(&buf).get_i32(), None);
+    }
+
+    #[test]
+    fn test_records() {
+        let mut buf = Vec::new();
+        encode_header(&mut buf);
+        encode_record(&mut buf, b"hello");
+        encode_record(&mut buf, b"");
+
+        let mut decoder = Decoder::new(&buf);
+        assert_eq!(decoder_header(&mut decoder), env!("CARGO_PKG_VERSION"));
+        assert_eq!(decode_record(&mut decoder).unwrap().unwrap(), b"hello");
+        assert_eq!(decode_record(&mut decoder).unwrap().unwrap(), b"");
+        assert!(decode_record(&mut decoder).is_none());
+
+        // flip a bit in the payload
+        let mut bad = buf.clone();
+        let index = bad.len() - 4 - 4 - 1 - 2;
+        bad[index] ^= 1;
+        let mut decoder = Decoder::new(&bad);
+        decoder_header(&mut decoder);
+        assert!(decode_record(&mut decoder).unwrap().is_err());
+
+        // truncated record
+        let mut decoder = Decoder::new(&buf[..buf.len() - 1]);
+        decoder_header(&mut decoder);
+        assert!(decode_record(&mut decoder).unwrap().is_ok());
+        assert!(decode_record(&mut decoder).unwrap().is_err());
+        assert!(decode_record(&mut decoder).is_none());
+    }
+
+    #[test]
+    fn test_bad_header() {
+        assert!(decode_header(&mut Decoder::new(b"")).is_err());
+        assert!(decode_header(&mut Decoder::new(b"NewGame 3\n")).is_err());
+        assert!(decode_header(&mut Decoder::new(b"CGEV\x02\x00\x00")).is_err());
+        assert!(decode_header(&mut Decoder::new(b"CGEV\x01\x00\x05abc")).is_err());
+        assert_eq!(
+            decode_header(&mut Decoder::new(b"CGEV\x01\x00\x03abc")).unwrap(),
+            "abc"
+        );
+    }
+
+    fn decoder_header(decoder: &mut Decoder) -> String {
+        decode_header(decoder).unwrap()
+    }
+}
@@ -0,0 +1,83 @@
+use super::binary::{self, Decoder};
+use file_scanner::Scanner;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Read, Result, Write};
+use std::str::FromStr;
+
+/// The type of damage an attack does. Characters may resist some brands.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Brand {
+    Cold,
+    Fire,
+    Physical,
+    Poison,
+}
+
+impl Brand {
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Brand> {
+        if let Some(name) = scanner.next() {
+            match name.as_str() {
+                "Cold" => Some(Brand::Cold),
+                "Fire" => Some(Brand::Fire),
+                "Physical" => Some(Brand::Physical),
+                "Poison" => Some(Brand::Poison),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
+        match self {
+            Brand::Cold => write!(w, "Cold"),
+            Brand::Fire => write!(w, "Fire"),
+            Brand::Physical => write!(w, "Physical"),
+            Brand::Poison => write!(w, "Poison"),
+        }
+    }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Brand> {
+        match decoder.get_u8() {
+            Some(0) => Some(Brand::Cold),
+            Some(1) => Some(Brand::Fire),
+            Some(2) => Some(Brand::Physical),
+            Some(3) => Some(Brand::Poison),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let tag = match self {
+            Brand::Cold => 0,
+            Brand::Fire => 1,
+            Brand::Physical => 2,
+            Brand::Poison => 3,
+        };
+        binary::put_u8(buf, tag);
+    }
+}
+
+impl fmt::Display for Brand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for Brand {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Brand, String> {
+        match text {
+            "Cold" => Ok(Brand::Cold),
+            "Fire" => Ok(Brand::Fire),
+            "Physical" => Ok(Brand::Physical),
+            "Poison" => Ok(Brand::Poison),
+            _ => Err(format!(
+                "expected Cold, Fire, Physical, or Poison but found '{}'",
+                text
+            )),
+        }
+    }
+}
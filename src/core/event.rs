@@ -1,46 +1,196 @@
+use super::binary::{self, Decoder};
 use super::*;
 use file_scanner::Scanner;
 use std::fmt;
 use std::fs::File;
-use std::io::{BufWriter, Result, Write};
+use std::io::{BufWriter, Read, Result, Write};
 
 /// This is used to record the facts that happened during a game.
 /// Services will update their state as events come in. Events are
 /// persisted so that games may be persisted and replayed for
 /// debugging purposes (or to recover from a crash).
-#[derive(Clone, Debug)]
+///
+/// For replay to work services must only use the RNG when handling
+/// an event and the outcome must be recorded in the payload of the
+/// events they queue up (e.g. SetPlayer records where find_char_loc
+/// placed the player).
+#[derive(Clone, Debug, PartialEq)]
 pub enum Event {
+    /// Text to show to the user, e.g. "The ay bites you (10 damage).".
+    AddMessage(Topic, String),
     AdvanceTime(Time),
-    // Attacked(ID, ID, DamageType, DamageAmount, Duration),	// need details so UI can render stuff like bolts or big strikes
+    /// Attacker, defender, the attack's verb (e.g. "bite"), its brand, and the
+    /// damage done after resistances are applied.
+    Attacked(Subject, Subject, String, Brand, i32),
+    /// The character's hit points dropped to zero.
+    Died(Subject),
     /// First event that fires when player enters a brand new level.
     NewBranch, // TODO: probably want to include a branch name
-    NewGame,
+    /// Follows NewGame with the player's name, species, and background.
+    NewCharacter(String, String, String),
+    /// First event in a game, includes the seed used for the RNG.
+    NewGame(u64),
     /// Fires after level is initialized to allow services to finish initializing.
     NewLevel,
-    // NewNPC(Point, ID, HPs),
+    /// Adds an NPC with a species and hit points that will be ready to act at the time.
+    NewNPC(Point, String, i32, Time),
+    /// Fires after the player has been placed on a new level so that NPCs can be added.
+    PopulateLevel,
     /// Update the current level with a name, size, and default terrain.
     /// SetTerrain events will follow this.
     ResetLevel(String, Size, Terrain),
 
+    /// Moves an NPC to a new location.
+    SetNPC(Subject, Point),
+
+    /// Time at which an NPC will next be able to act.
+    SetNPCReady(Subject, Time),
+
     SetPlayer(Point),
 
+    /// Time at which the player will next be able to act.
+    SetPlayerReady(Time),
+
     SetTerrain(Point, Terrain),
 }
 
+/// Pending events are executed in phase order and, within a phase, in the order
+/// they were queued. This allows services to queue up events without worrying
+/// about what other services have queued, e.g. NewLevel will always execute
+/// after the SetTerrain events that build the level.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Phase {
+    /// Starting a new game or branch.
+    Setup,
+
+    /// Building a level.
+    Level,
+
+    /// The level has been built.
+    LevelReady,
+
+    /// Characters are placed or act.
+    Characters,
+
+    /// Levels are populated with NPCs once the player has been placed.
+    Populate,
+
+    /// Time only advances (and the UI only renders) once everything else has executed.
+    Time,
+}
+
+pub const NUM_PHASES: usize = Phase::Time as usize + 1;
+
+/// Every name that Event::name can return, sorted.
+pub const EVENT_NAMES: [&str; 16] = [
+    "AddMessage",
+    "AdvanceTime",
+    "Attacked",
+    "Died",
+    "NewBranch",
+    "NewCharacter",
+    "NewGame",
+    "NewLevel",
+    "NewNPC",
+    "PopulateLevel",
+    "ResetLevel",
+    "SetNPC",
+    "SetNPCReady",
+    "SetPlayer",
+    "SetPlayerReady",
+    "SetTerrain",
+];
+
 impl Event {
-    pub fn from_saved(scanner: &mut Scanner<File>) -> Option<Event> {
+    pub fn phase(&self) -> Phase {
+        match self {
+            Event::AddMessage(_, _) => Phase::Characters,
+            Event::AdvanceTime(_) => Phase::Time,
+            Event::Attacked(_, _, _, _, _) => Phase::Characters,
+            Event::Died(_) => Phase::Characters,
+            Event::NewBranch => Phase::Setup,
+            Event::NewCharacter(_, _, _) => Phase::Setup,
+            Event::NewGame(_) => Phase::Setup,
+            Event::NewLevel => Phase::LevelReady,
+            Event::NewNPC(_, _, _, _) => Phase::Characters,
+            Event::PopulateLevel => Phase::Populate,
+            Event::ResetLevel(_, _, _) => Phase::Level,
+            Event::SetNPC(_, _) => Phase::Characters,
+            Event::SetNPCReady(_, _) => Phase::Characters,
+            Event::SetPlayer(_) => Phase::Characters,
+            Event::SetPlayerReady(_) => Phase::Characters,
+            Event::SetTerrain(_, _) => Phase::Level,
+        }
+    }
+
+    /// Returns the name of the variant, e.g. "SetPlayer".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::AddMessage(_, _) => "AddMessage",
+            Event::AdvanceTime(_) => "AdvanceTime",
+            Event::Attacked(_, _, _, _, _) => "Attacked",
+            Event::Died(_) => "Died",
+            Event::NewBranch => "NewBranch",
+            Event::NewCharacter(_, _, _) => "NewCharacter",
+            Event::NewGame(_) => "NewGame",
+            Event::NewLevel => "NewLevel",
+            Event::NewNPC(_, _, _, _) => "NewNPC",
+            Event::PopulateLevel => "PopulateLevel",
+            Event::ResetLevel(_, _, _) => "ResetLevel",
+            Event::SetNPC(_, _) => "SetNPC",
+            Event::SetNPCReady(_, _) => "SetNPCReady",
+            Event::SetPlayer(_) => "SetPlayer",
+            Event::SetPlayerReady(_) => "SetPlayerReady",
+            Event::SetTerrain(_, _) => "SetTerrain",
+        }
+    }
+
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Event> {
         if let Some(name) = scanner.next() {
             match name.as_str() {
-                "AdvanceTime" => {
-                    if let Some(time) = Time::from_saved(scanner) {
-                        Some(Event::AdvanceTime(time))
-                    } else {
-                        None
+                "AddMessage" => match (Topic::from_saved(scanner), next_string(scanner)) {
+                    (Some(topic), Some(text)) => Some(Event::AddMessage(topic, text)),
+                    _ => None,
+                },
+                "AdvanceTime" => Time::from_saved(scanner).map(Event::AdvanceTime),
+                "Attacked" => match (
+                    Subject::from_saved(scanner),
+                    Subject::from_saved(scanner),
+                    next_string(scanner),
+                    Brand::from_saved(scanner),
+                    scanner.next_int(),
+                ) {
+                    (Some(attacker), Some(defender), Some(verb), Some(brand), Some(damage)) => {
+                        Some(Event::Attacked(attacker, defender, verb, brand, damage))
                     }
-                }
+                    _ => None,
+                },
+                "Died" => Subject::from_saved(scanner).map(Event::Died),
                 "NewBranch" => Some(Event::NewBranch),
-                "NewGame" => Some(Event::NewGame),
+                "NewCharacter" => match (
+                    next_string(scanner),
+                    next_string(scanner),
+                    next_string(scanner),
+                ) {
+                    (Some(name), Some(species), Some(background)) => {
+                        Some(Event::NewCharacter(name, species, background))
+                    }
+                    _ => None,
+                },
+                "NewGame" => scanner.next_int().map(Event::NewGame),
                 "NewLevel" => Some(Event::NewLevel),
+                "NewNPC" => match (
+                    Point::from_saved(scanner),
+                    next_string(scanner),
+                    scanner.next_int(),
+                    Time::from_saved(scanner),
+                ) {
+                    (Some(loc), Some(species), Some(hps), Some(ready)) => {
+                        Some(Event::NewNPC(loc, species, hps, ready))
+                    }
+                    _ => None,
+                },
+                "PopulateLevel" => Some(Event::PopulateLevel),
                 "ResetLevel" => {
                     match (
                         next_string(scanner),
@@ -53,7 +203,20 @@ impl Event {
                         _ => None,
                     }
                 }
-                handle the other cases
+                "SetNPC" => match (Subject::from_saved(scanner), Point::from_saved(scanner)) {
+                    (Some(name), Some(loc)) => Some(Event::SetNPC(name, loc)),
+                    _ => None,
+                },
+                "SetNPCReady" => match (Subject::from_saved(scanner), Time::from_saved(scanner)) {
+                    (Some(name), Some(time)) => Some(Event::SetNPCReady(name, time)),
+                    _ => None,
+                },
+                "SetPlayer" => Point::from_saved(scanner).map(Event::SetPlayer),
+                "SetPlayerReady" => Time::from_saved(scanner).map(Event::SetPlayerReady),
+                "SetTerrain" => match (Point::from_saved(scanner), Terrain::from_saved(scanner)) {
+                    (Some(loc), Some(terrain)) => Some(Event::SetTerrain(loc, terrain)),
+                    _ => None,
+                },
                 _ => None,
             }
         } else {
@@ -63,23 +226,79 @@ impl Event {
 
     pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
         match self {
+            Event::AddMessage(topic, text) => {
+                write!(w, "AddMessage ")?;
+                topic.write(w)?;
+                write!(w, " ")?;
+                write_string(w, text)
+            }
             Event::AdvanceTime(time) => {
                 write!(w, "AdvanceTime ")?;
                 time.write(w)
             }
+            Event::Attacked(attacker, defender, verb, brand, damage) => {
+                write!(w, "Attacked ")?;
+                attacker.write(w)?;
+                write!(w, " ")?;
+                defender.write(w)?;
+                write!(w, " ")?;
+                write_string(w, verb)?;
+                write!(w, " ")?;
+                brand.write(w)?;
+                write!(w, " {}", damage)
+            }
+            Event::Died(name) => {
+                write!(w, "Died ")?;
+                name.write(w)
+            }
             Event::NewBranch => write!(w, "NewBranch"),
-            Event::NewGame => write!(w, "NewGame"),
+            Event::NewCharacter(name, species, background) => {
+                write!(w, "NewCharacter ")?;
+                write_string(w, name)?;
+                write!(w, " ")?;
+                write_string(w, species)?;
+                write!(w, " ")?;
+                write_string(w, background)
+            }
+            Event::NewGame(seed) => write!(w, "NewGame {}", seed),
             Event::NewLevel => write!(w, "NewLevel"),
+            Event::NewNPC(loc, species, hps, ready) => {
+                write!(w, "NewNPC ")?;
+                loc.write(w)?;
+                write!(w, " ")?;
+                write_string(w, species)?;
+                write!(w, " {} ", hps)?;
+                ready.write(w)
+            }
+            Event::PopulateLevel => write!(w, "PopulateLevel"),
             Event::ResetLevel(name, size, terrain) => {
-                write!(w, "ResetLevel \"{}\" ", name)?; // TODO: need to ensure level name never has a " character
+                write!(w, "ResetLevel ")?;
+                write_string(w, name)?;
+                write!(w, " ")?;
                 size.write(w)?;
                 write!(w, " ")?;
                 terrain.write(w)
             }
+            Event::SetNPC(name, loc) => {
+                write!(w, "SetNPC ")?;
+                name.write(w)?;
+                write!(w, " ")?;
+                loc.write(w)
+            }
+            Event::SetNPCReady(name, time) => {
+                write!(w, "SetNPCReady ")?;
+                name.write(w)?;
+                write!(w, " ")?;
+                time.write(w)
+            }
             Event::SetPlayer(loc) => {
                 write!(w, "SetPlayer ")?;
                 loc.write(w)
             }
+            Event::SetPlayerReady(time) => {
+                write!(w, "SetPlayerReady ")?;
+                time.write(w)
+            }
             Event::SetTerrain(loc, terrain) => {
                 write!(w, "SetTerrain ")?;
                 loc.write(w)?;
@@ -88,17 +307,173 @@ impl Event {
             }
         }
     }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Event> {
+        match decoder.get_u8()? {
+            0 => Time::decode(decoder).map(Event::AdvanceTime),
+            1 => Some(Event::NewBranch),
+            2 => decoder.get_uvarint().map(Event::NewGame),
+            3 => Some(Event::NewLevel),
+            4 => match (
+                decoder.get_str(),
+                Size::decode(decoder),
+                Terrain::decode(decoder),
+            ) {
+                (Some(name), Some(size), Some(terrain)) => {
+                    Some(Event::ResetLevel(name, size, terrain))
+                }
+                _ => None,
+            },
+            5 => Point::decode(decoder).map(Event::SetPlayer),
+            6 => Time::decode(decoder).map(Event::SetPlayerReady),
+            7 => match (Point::decode(decoder), Terrain::decode(decoder)) {
+                (Some(loc), Some(terrain)) => Some(Event::SetTerrain(loc, terrain)),
+                _ => None,
+            },
+            8 => match (
+                Point::decode(decoder),
+                decoder.get_str(),
+                decoder.get_i32(),
+                Time::decode(decoder),
+            ) {
+                (Some(loc), Some(species), Some(hps), Some(ready)) => {
+                    Some(Event::NewNPC(loc, species, hps, ready))
+                }
+                _ => None,
+            },
+            9 => Some(Event::PopulateLevel),
+            10 => match (Subject::decode(decoder), Time::decode(decoder)) {
+                (Some(name), Some(time)) => Some(Event::SetNPCReady(name, time)),
+                _ => None,
+            },
+            11 => match (Subject::decode(decoder), Point::decode(decoder)) {
+                (Some(name), Some(loc)) => Some(Event::SetNPC(name, loc)),
+                _ => None,
+            },
+            12 => match (
+                Subject::decode(decoder),
+                Subject::decode(decoder),
+                decoder.get_str(),
+                Brand::decode(decoder),
+                decoder.get_i32(),
+            ) {
+                (Some(attacker), Some(defender), Some(verb), Some(brand), Some(damage)) => {
+                    Some(Event::Attacked(attacker, defender, verb, brand, damage))
+                }
+                _ => None,
+            },
+            13 => Subject::decode(decoder).map(Event::Died),
+            14 => match (Topic::decode(decoder), decoder.get_str()) {
+                (Some(topic), Some(text)) => Some(Event::AddMessage(topic, text)),
+                _ => None,
+            },
+            15 => match (decoder.get_str(), decoder.get_str(), decoder.get_str()) {
+                (Some(name), Some(species), Some(background)) => {
+                    Some(Event::NewCharacter(name, species, background))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Note that tags should not be changed without bumping binary::FORMAT_VERSION.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Event::AdvanceTime(time) => {
+                binary::put_u8(buf, 0);
+                time.encode(buf);
+            }
+            Event::NewBranch => binary::put_u8(buf, 1),
+            Event::NewGame(seed) => {
+                binary::put_u8(buf, 2);
+                binary::put_uvarint(buf, *seed);
+            }
+            Event::NewLevel => binary::put_u8(buf, 3),
+            Event::ResetLevel(name, size, terrain) => {
+                binary::put_u8(buf, 4);
+                binary::put_str(buf, name);
+                size.encode(buf);
+                terrain.encode(buf);
+            }
+            Event::SetPlayer(loc) => {
+                binary::put_u8(buf, 5);
+                loc.encode(buf);
+            }
+            Event::SetPlayerReady(time) => {
+                binary::put_u8(buf, 6);
+                time.encode(buf);
+            }
+            Event::SetTerrain(loc, terrain) => {
+                binary::put_u8(buf, 7);
+                loc.encode(buf);
+                terrain.encode(buf);
+            }
+            Event::NewNPC(loc, species, hps, ready) => {
+                binary::put_u8(buf, 8);
+                loc.encode(buf);
+                binary::put_str(buf, species);
+                binary::put_ivarint(buf, i64::from(*hps));
+                ready.encode(buf);
+            }
+            Event::PopulateLevel => binary::put_u8(buf, 9),
+            Event::SetNPCReady(name, time) => {
+                binary::put_u8(buf, 10);
+                name.encode(buf);
+                time.encode(buf);
+            }
+            Event::SetNPC(name, loc) => {
+                binary::put_u8(buf, 11);
+                name.encode(buf);
+                loc.encode(buf);
+            }
+            Event::Attacked(attacker, defender, verb, brand, damage) => {
+                binary::put_u8(buf, 12);
+                attacker.encode(buf);
+                defender.encode(buf);
+                binary::put_str(buf, verb);
+                brand.encode(buf);
+                binary::put_ivarint(buf, i64::from(*damage));
+            }
+            Event::Died(name) => {
+                binary::put_u8(buf, 13);
+                name.encode(buf);
+            }
+            Event::AddMessage(topic, text) => {
+                binary::put_u8(buf, 14);
+                topic.encode(buf);
+                binary::put_str(buf, text);
+            }
+            Event::NewCharacter(name, species, background) => {
+                binary::put_u8(buf, 15);
+                binary::put_str(buf, name);
+                binary::put_str(buf, species);
+                binary::put_str(buf, background);
+            }
+        }
+    }
 }
 
 impl fmt::Display for Event {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Event::AddMessage(t, s) => write!(f, "AddMessage({}, {})", t, s),
             Event::AdvanceTime(t) => write!(f, "AdvanceTime({})", t),
+            Event::Attacked(a, d, v, b, n) => {
+                write!(f, "Attacked({}, {}, {}, {}, {})", a, d, v, b, n)
+            }
+            Event::Died(n) => write!(f, "Died({})", n),
             Event::NewBranch => write!(f, "NewBranch"),
-            Event::NewGame => write!(f, "NewGame"),
+            Event::NewCharacter(n, s, b) => write!(f, "NewCharacter({}, {}, {})", n, s, b),
+            Event::NewGame(seed) => write!(f, "NewGame({})", seed),
             Event::NewLevel => write!(f, "NewLevel"),
+            Event::NewNPC(l, s, h, r) => write!(f, "NewNPC({}, {}, {}, {})", l, s, h, r),
+            Event::PopulateLevel => write!(f, "PopulateLevel"),
             Event::ResetLevel(n, s, t) => write!(f, "ResetLevel({}, {}, {})", n, s, t),
+            Event::SetNPC(n, l) => write!(f, "SetNPC({}, {})", n, l),
+            Event::SetNPCReady(n, t) => write!(f, "SetNPCReady({}, {})", n, t),
             Event::SetPlayer(l) => write!(f, "SetPlayer({})", l),
+            Event::SetPlayerReady(t) => write!(f, "SetPlayerReady({})", t),
             Event::SetTerrain(l, t) => write!(f, "SetTerrain({}, {})", l, t),
         }
     }
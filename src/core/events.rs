@@ -1,28 +1,217 @@
+use super::binary::{self, Decoder};
+use super::event::NUM_PHASES;
 use super::*;
 
+use file_scanner::Scanner;
+use std::collections::VecDeque;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufWriter, Result, Write};
+use std::io::{BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How events are written to saved games and journals.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EventFormat {
+    /// One event per line, handy for debugging.
+    Text,
+
+    /// Compact format with checksums, see the binary module.
+    Binary,
+}
 
 // Events which have executed.
 pub struct ExecutedEvents {
     events: Vec<Event>, // TODO: use a deque?
+    journal: Option<BufWriter<File>>,
+    journal_format: EventFormat,
+    journal_error: Option<Error>,
 }
 
-// [`Event`]s which are pending execution.
+// [`Event`]s which are pending execution, see [`Phase`] for the order in which they execute.
 pub struct PendingEvents {
-    events: Vec<Event>, // TODO: use a deque?
+    queues: [VecDeque<Event>; NUM_PHASES],
+}
+
+impl EventFormat {
+    /// Binary files start with a magic number, anything else is assumed to be text.
+    pub fn detect(bytes: &[u8]) -> EventFormat {
+        if binary::has_magic(bytes) {
+            EventFormat::Binary
+        } else {
+            EventFormat::Text
+        }
+    }
+}
+
+impl fmt::Display for EventFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventFormat::Text => write!(f, "text"),
+            EventFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+impl FromStr for EventFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<EventFormat, String> {
+        match s {
+            "text" => Ok(EventFormat::Text),
+            "binary" => Ok(EventFormat::Binary),
+            _ => Err(format!("expected text or binary but found '{}'", s)),
+        }
+    }
 }
 
 impl ExecutedEvents {
     pub fn new() -> ExecutedEvents {
-        ExecutedEvents { events: Vec::new() }
+        ExecutedEvents {
+            events: Vec::new(),
+            journal: None,
+            journal_format: EventFormat::Text,
+            journal_error: None,
+        }
+    }
+
+    /// Starts writing events to a journal file as they are appended so that the game
+    /// can be recovered if we crash. The file is truncated and then the events that
+    /// have already been appended are written to it.
+    pub fn start_journal(&mut self, path: &Path, format: EventFormat) -> Result<()> {
+        let file = File::create(path)?;
+        let mut journal = BufWriter::new(file);
+        match format {
+            EventFormat::Text => self.save(&mut journal)?,
+            EventFormat::Binary => self.save_binary(&mut journal)?,
+        }
+        journal.flush()?;
+        self.journal = Some(journal);
+        self.journal_format = format;
+        Ok(())
+    }
+
+    /// Loads events written by [`ExecutedEvents::save`]. Each line must contain exactly
+    /// one event: blank lines are ignored but anything else that doesn't parse is an
+    /// error (including trailing tokens and truncated events).
+    pub fn load<R: Read>(scanner: &mut Scanner<R>) -> Result<ExecutedEvents> {
+        ExecutedEvents::load_lines(scanner, false)
+    }
+
+    /// Like load except that it's used for journals written by a game that crashed.
+    /// These may end with a partially written event which is ignored.
+    pub fn recover<R: Read>(scanner: &mut Scanner<R>) -> Result<ExecutedEvents> {
+        ExecutedEvents::load_lines(scanner, true)
+    }
+
+    /// Loads events written by [`ExecutedEvents::save_binary`].
+    pub fn load_binary(bytes: &[u8]) -> Result<ExecutedEvents> {
+        ExecutedEvents::load_records(bytes, false)
+    }
+
+    /// Binary version of recover: a truncated or corrupted last record is ignored.
+    pub fn recover_binary(bytes: &[u8]) -> Result<ExecutedEvents> {
+        ExecutedEvents::load_records(bytes, true)
+    }
+
+    fn load_lines<R: Read>(scanner: &mut Scanner<R>, torn_tail: bool) -> Result<ExecutedEvents> {
+        let mut events = ExecutedEvents::new();
+        let mut line_num = 0;
+        while let Some(line) = scanner.next_line() {
+            line_num += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut line_scanner = Scanner::new(line.as_bytes());
+            match Event::from_saved(&mut line_scanner) {
+                // Note that Scanner::next returns an empty string (not None) at EOF.
+                Some(event) if line_scanner.next().is_none_or(|t| t.is_empty()) => {
+                    events.events.push(event)
+                }
+                _ if torn_tail && scanner.next_line().is_none() => break,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("bad event on line {}: {}", line_num, line),
+                    ))
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    fn load_records(bytes: &[u8], torn_tail: bool) -> Result<ExecutedEvents> {
+        let mut events = ExecutedEvents::new();
+        let mut decoder = Decoder::new(bytes);
+        binary::decode_header(&mut decoder)?;
+
+        let mut record_num = 0;
+        while let Some(record) = binary::decode_record(&mut decoder) {
+            record_num += 1;
+            let result = record.and_then(|payload| {
+                let mut payload = Decoder::new(payload);
+                match Event::decode(&mut payload) {
+                    Some(event) if payload.is_empty() => Ok(event),
+                    _ => Err(Error::new(ErrorKind::InvalidData, "bad event")),
+                }
+            });
+            match result {
+                Ok(event) => events.events.push(event),
+                // Only the last record can have been torn by a crash.
+                Err(_) if torn_tail && decoder.is_empty() => break,
+                Err(err) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("record {}: {}", record_num, err),
+                    ))
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Event> {
+        self.events.iter()
     }
 
     pub fn append(&mut self, event: &Event) {
-        // TODO: persist it (probably want to flush too)
+        if let Some(journal) = &mut self.journal {
+            // We don't want to kill the game if the journal can't be written
+            // so we'll stop journaling and report the error on the next flush.
+            let result = match self.journal_format {
+                EventFormat::Text => event.write(journal).and_then(|_| writeln!(journal)),
+                EventFormat::Binary => write_record(journal, event),
+            };
+            if let Err(err) = result {
+                self.journal = None;
+                self.journal_error = Some(err);
+            }
+        }
         self.events.push(event.clone());
     }
 
+    /// Should be called at points where the game could be recovered from, i.e.
+    /// when there are no pending events.
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some(err) = self.journal_error.take() {
+            return Err(err);
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.flush()
+        } else {
+            Ok(())
+        }
+    }
+
     // We don't use the Write trait to avoid the dynamic dispatch that will
     // incur (altho that would make unit tests a bit nicer).
     pub fn save(&self, writer: &mut BufWriter<File>) -> Result<()> {
@@ -34,23 +223,68 @@ impl ExecutedEvents {
         }
         Ok(())
     }
+
+    pub fn save_binary(&self, writer: &mut BufWriter<File>) -> Result<()> {
+        let mut header = Vec::new();
+        binary::encode_header(&mut header);
+        writer.write_all(&header)?;
+        for event in self.events.iter() {
+            write_record(writer, event)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_record(writer: &mut BufWriter<File>, event: &Event) -> Result<()> {
+    let mut payload = Vec::new();
+    event.encode(&mut payload);
+
+    let mut record = Vec::with_capacity(payload.len() + 8);
+    binary::encode_record(&mut record, &payload);
+    writer.write_all(&record)
+}
+
+impl Default for ExecutedEvents {
+    fn default() -> ExecutedEvents {
+        ExecutedEvents::new()
+    }
 }
 
 impl PendingEvents {
     pub fn new() -> PendingEvents {
-        PendingEvents { events: Vec::new() }
+        PendingEvents {
+            queues: Default::default(),
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.events.is_empty()
+        self.queues.iter().all(|queue| queue.is_empty())
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
     }
 
     pub fn push_back(&mut self, event: Event) {
-        self.events.push(event);
+        self.queues[event.phase() as usize].push_back(event);
+    }
+
+    /// Returns the event that will execute next.
+    pub fn front(&self) -> Option<&Event> {
+        self.queues.iter().find_map(|queue| queue.front())
     }
 
     pub fn pop_front(&mut self) -> Event {
-        self.events.remove(0)
+        self.queues
+            .iter_mut()
+            .find_map(|queue| queue.pop_front())
+            .expect("pop_front was called on empty PendingEvents")
+    }
+}
+
+impl Default for PendingEvents {
+    fn default() -> PendingEvents {
+        PendingEvents::new()
     }
 }
 
@@ -58,23 +292,66 @@ impl PendingEvents {
 mod tests {
     use super::*;
 
-    fn save_events(suffix: &str) -> String {
-        let path = format!("/tmp/crippled-god-{}.txt", suffix);
-        let f = File::create(&path).unwrap();
-        let mut f = BufWriter::new(f);
+    fn all_events() -> ExecutedEvents {
         let mut events = ExecutedEvents::new();
+        events.append(&Event::AddMessage(
+            Topic::PlayerIsDamaged,
+            "The ay bites you.".to_string(),
+        ));
         events.append(&Event::AdvanceTime(Time::from_secs(1.2)));
+        events.append(&Event::Attacked(
+            PLAYER.clone(),
+            Subject::new_unique("ay-3"),
+            "hit".to_string(),
+            Brand::Physical,
+            15,
+        ));
+        events.append(&Event::Died(Subject::new_unique("ay-3")));
         events.append(&Event::NewBranch);
-        events.append(&Event::NewGame);
+        events.append(&Event::NewCharacter(
+            "Fiddler".to_string(),
+            "human".to_string(),
+            "Sapper".to_string(),
+        ));
+        events.append(&Event::NewGame(42));
         events.append(&Event::NewLevel);
+        events.append(&Event::NewNPC(
+            Point::new(2, 1),
+            "ay".to_string(),
+            14,
+            Time::from_secs(1.1),
+        ));
+        events.append(&Event::PopulateLevel);
         events.append(&Event::ResetLevel(
             "town".to_string(),
             Size::new(12, 10),
             Terrain::Wall,
         ));
+        events.append(&Event::SetNPC(
+            Subject::new_unique("ay-3"),
+            Point::new(7, 8),
+        ));
+        events.append(&Event::SetNPCReady(
+            Subject::new_unique("ay-3"),
+            Time::from_secs(2.0),
+        ));
         events.append(&Event::SetPlayer(Point::new(5, 4)));
+        events.append(&Event::SetPlayerReady(Time::from_secs(3.0)));
         events.append(&Event::SetTerrain(Point::new(2, 3), Terrain::Ground));
-        events.save(&mut f).expect("save events failed");
+        events
+    }
+
+    #[test]
+    fn test_event_names() {
+        let names: Vec<&str> = all_events().iter().map(|event| event.name()).collect();
+        assert_eq!(names, EVENT_NAMES);
+    }
+
+    fn save_events(suffix: &str) -> String {
+        let path = format!("/tmp/crippled-god-{}.txt", suffix);
+        let f = File::create(&path).unwrap();
+        let mut f = BufWriter::new(f);
+        all_events().save(&mut f).expect("save events failed");
         f.flush().expect("flush failed");
         path
     }
@@ -85,34 +362,439 @@ mod tests {
         let contents = std::fs::read_to_string(&path).unwrap();
         assert_eq!(
             contents,
-            "AdvanceTime 12
+            "AddMessage PlayerIsDamaged \"The ay bites you.\"
+AdvanceTime 12
+Attacked \"player\" \"ay-3\" \"hit\" Physical 15
+Died \"ay-3\"
 NewBranch
-NewGame
+NewCharacter \"Fiddler\" \"human\" \"Sapper\"
+NewGame 42
 NewLevel
+NewNPC 2 1 \"ay\" 14 11
+PopulateLevel
 ResetLevel \"town\" 12 10 Wall
+SetNPC \"ay-3\" 7 8
+SetNPCReady \"ay-3\" 20
 SetPlayer 5 4
+SetPlayerReady 30
 SetTerrain 2 3 Ground
 "
         );
     }
 
+    fn load_events(path: &str) -> Result<ExecutedEvents> {
+        let f = File::open(path).unwrap();
+        let mut scanner = Scanner::new(f);
+        ExecutedEvents::load(&mut scanner)
+    }
+
+    fn load_str(suffix: &str, contents: &str) -> Result<ExecutedEvents> {
+        let path = format!("/tmp/crippled-god-{}.txt", suffix);
+        std::fs::write(&path, contents).unwrap();
+        load_events(&path)
+    }
+
     #[test]
     fn test_round_trip() {
-        // load events
-        // do a match of the events
-        //      verify args are as expected
-        //      append a token
-        // verify string is as expected
         let path = save_events("round-trip");
+        let events = load_events(&path).expect("load events failed");
+
+        let mut tokens = Vec::new();
+        for event in events.iter() {
+            match event {
+                Event::AddMessage(topic, text) => {
+                    assert_eq!(*topic, Topic::PlayerIsDamaged);
+                    assert_eq!(text, "The ay bites you.");
+                    tokens.push("AM");
+                }
+                Event::AdvanceTime(time) => {
+                    assert_eq!(*time, Time::from_secs(1.2));
+                    tokens.push("AT");
+                }
+                Event::Attacked(attacker, defender, verb, brand, damage) => {
+                    assert_eq!(*attacker, *PLAYER);
+                    assert_eq!(*defender, Subject::new_unique("ay-3"));
+                    assert_eq!(verb, "hit");
+                    assert_eq!(*brand, Brand::Physical);
+                    assert_eq!(*damage, 15);
+                    tokens.push("AK");
+                }
+                Event::Died(name) => {
+                    assert_eq!(*name, Subject::new_unique("ay-3"));
+                    tokens.push("DI");
+                }
+                Event::NewBranch => tokens.push("NB"),
+                Event::NewCharacter(name, species, background) => {
+                    assert_eq!(name, "Fiddler");
+                    assert_eq!(species, "human");
+                    assert_eq!(background, "Sapper");
+                    tokens.push("NC");
+                }
+                Event::NewGame(seed) => {
+                    assert_eq!(*seed, 42);
+                    tokens.push("NG");
+                }
+                Event::NewLevel => tokens.push("NL"),
+                Event::NewNPC(loc, species, hps, ready) => {
+                    assert_eq!(*loc, Point::new(2, 1));
+                    assert_eq!(species, "ay");
+                    assert_eq!(*hps, 14);
+                    assert_eq!(*ready, Time::from_secs(1.1));
+                    tokens.push("NN");
+                }
+                Event::PopulateLevel => tokens.push("PL"),
+                Event::ResetLevel(name, size, terrain) => {
+                    assert_eq!(name, "town");
+                    assert_eq!(*size, Size::new(12, 10));
+                    assert_eq!(*terrain, Terrain::Wall);
+                    tokens.push("RL");
+                }
+                Event::SetNPC(name, loc) => {
+                    assert_eq!(*name, Subject::new_unique("ay-3"));
+                    assert_eq!(*loc, Point::new(7, 8));
+                    tokens.push("SN");
+                }
+                Event::SetNPCReady(name, time) => {
+                    assert_eq!(*name, Subject::new_unique("ay-3"));
+                    assert_eq!(*time, Time::from_secs(2.0));
+                    tokens.push("NR");
+                }
+                Event::SetPlayer(loc) => {
+                    assert_eq!(*loc, Point::new(5, 4));
+                    tokens.push("SP");
+                }
+                Event::SetPlayerReady(time) => {
+                    assert_eq!(*time, Time::from_secs(3.0));
+                    tokens.push("SR");
+                }
+                Event::SetTerrain(loc, terrain) => {
+                    assert_eq!(*loc, Point::new(2, 3));
+                    assert_eq!(*terrain, Terrain::Ground);
+                    tokens.push("ST");
+                }
+            }
+        }
+        assert_eq!(
+            tokens.join(" "),
+            "AM AT AK DI NB NC NG NL NN PL RL SN NR SP SR ST"
+        );
+    }
+
+    #[test]
+    fn test_level_name_with_spaces() {
+        let events = load_str("spaces", "ResetLevel \"Moon's Spawn\" 3 4 Ground\n").unwrap();
+        let events: Vec<Event> = events.iter().cloned().collect();
+        assert_eq!(
+            events,
+            vec![Event::ResetLevel(
+                "Moon's Spawn".to_string(),
+                Size::new(3, 4),
+                Terrain::Ground
+            )]
+        );
+    }
+
+    #[test]
+    fn test_escaped_strings() {
+        let names = [
+            "\"",
+            "\"Fid\"",
+            "a\\\"b",
+            "\\",
+            "back\\slash\\\\",
+            "\"\"",
+            " spaced ",
+            "",
+        ];
+        let mut events = ExecutedEvents::new();
+        for name in names.iter() {
+            events.append(&Event::NewCharacter(
+                name.to_string(),
+                "human".to_string(),
+                "Sapper".to_string(),
+            ));
+        }
+        let path = "/tmp/crippled-god-escaped.txt";
+        let mut f = BufWriter::new(File::create(path).unwrap());
+        events.save(&mut f).expect("save events failed");
+        f.flush().expect("flush failed");
+
+        let loaded: Vec<Event> = load_events(path).unwrap().iter().cloned().collect();
+        let expected: Vec<Event> = events.iter().cloned().collect();
+        assert_eq!(loaded, expected);
+
+        let text = "NewCharacter \"Fid\"dler\" \"human\" \"Sapper\"\n";
+        assert!(load_str("unescaped", text).is_err());
+        let text = "NewCharacter \"Fid\\dler\" \"human\" \"Sapper\"\n";
+        assert!(load_str("bad-escape", text).is_err());
+    }
+
+    #[test]
+    fn test_empty() {
+        let events = load_str("empty", "").unwrap();
+        assert!(events.is_empty());
+
+        let events = load_str("blank", "\n  \n").unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert!(load_str("truncated0", "NewGame\n").is_err());
+        assert!(load_str("truncated1", "NewGame 1\nSetTerrain 2 3\n").is_err());
+        assert!(load_str("truncated2", "NewGame 1\nSetTerrain 2 3 Gro").is_err());
+        assert!(load_str("truncated3", "ResetLevel \"town\" 12").is_err());
+        assert!(load_str("truncated4", "ResetLevel \"town").is_err());
+    }
+
+    #[test]
+    fn test_garbage() {
+        assert!(load_str("garbage1", "hello world\n").is_err());
+        assert!(load_str("garbage2", "SetPlayer x y\n").is_err());
+        assert!(load_str("garbage3", "SetTerrain 1 2 Lava\n").is_err());
+        assert!(load_str("garbage4", "ResetLevel town 12 10 Wall\n").is_err());
+    }
+
+    #[test]
+    fn test_trailing_tokens() {
+        let err = load_str("trailing", "NewGame 1\nNewLevel extra\n")
+            .err()
+            .expect("expected an error");
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+
+        assert!(load_str("trailing2", "SetPlayer 1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn test_journal() {
+        let path = std::path::PathBuf::from("/tmp/crippled-god-journal.txt");
+        let mut events = ExecutedEvents::new();
+        events.append(&Event::NewGame(3));
+        events.start_journal(&path, EventFormat::Text).unwrap();
+        events.append(&Event::SetPlayer(Point::new(1, 2)));
+        events.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "NewGame 3\nSetPlayer 1 2\n");
+
+        events.append(&Event::NewLevel);
+        events.flush().unwrap();
+        let loaded = load_events(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.len(), 3);
+    }
+
+    #[test]
+    fn test_recover() {
+        // A crash in the middle of writing an event can leave a partial event
+        // at the end of the journal.
+        let path = "/tmp/crippled-god-torn.txt";
+        std::fs::write(path, "NewGame 3\nSetPlayer 1 2\nSetTerrain 5").unwrap();
+        assert!(load_events(path).is_err());
+
+        let f = File::open(path).unwrap();
+        let events = ExecutedEvents::recover(&mut Scanner::new(f)).unwrap();
+        assert_eq!(events.len(), 2);
+
+        // But recover should only ignore problems with the last line.
+        let path = "/tmp/crippled-god-torn2.txt";
+        std::fs::write(path, "NewGame 3\nSetPlayer 1\nNewLevel\n").unwrap();
+        let f = File::open(path).unwrap();
+        assert!(ExecutedEvents::recover(&mut Scanner::new(f)).is_err());
+    }
+
+    #[test]
+    fn test_no_trailing_newline() {
+        let events = load_str("no-newline", "NewGame 1\nSetPlayer 1 2").unwrap();
+        assert_eq!(events.len(), 2);
+    }
+
+    fn save_binary(suffix: &str, events: &ExecutedEvents) -> Vec<u8> {
+        let path = format!("/tmp/crippled-god-{}.bin", suffix);
+        let f = File::create(&path).unwrap();
+        let mut f = BufWriter::new(f);
+        events.save_binary(&mut f).expect("save events failed");
+        f.flush().expect("flush failed");
+        std::fs::read(&path).unwrap()
+    }
+
+    fn to_vec(events: &ExecutedEvents) -> Vec<Event> {
+        events.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut events = all_events();
+        events.append(&Event::ResetLevel(
+            "\"Moon's\" Spawn".to_string(),
+            Size::new(300, 200),
+            Terrain::DeepWater,
+        ));
+        events.append(&Event::SetPlayer(Point::new(-5, 100_000)));
+        let bytes = save_binary("round-trip", &events);
+        assert_eq!(EventFormat::detect(&bytes), EventFormat::Binary);
+
+        let loaded = ExecutedEvents::load_binary(&bytes).unwrap();
+        assert_eq!(to_vec(&loaded), to_vec(&events));
+    }
+
+    #[test]
+    fn test_binary_is_smaller() {
+        let text = std::fs::read(save_events("size-text")).unwrap();
+        let bytes = save_binary("size-binary", &all_events());
+        assert_eq!(EventFormat::detect(&text), EventFormat::Text);
+        assert!(
+            bytes.len() < text.len(),
+            "{} >= {}",
+            bytes.len(),
+            text.len()
+        );
+    }
+
+    #[test]
+    fn test_binary_corrupt() {
+        let bytes = save_binary("corrupt", &all_events());
 
-        let mut tokens = "";
-        assert_eq!(tokens, "AT NB NG NL RL SP, ST");
+        // the last record is SetTerrain which is a tag, three varints, and the checksum
+        let mut bad = bytes.clone();
+        let index = bad.len() - 4 - 2;
+        bad[index] ^= 0x10;
+        let err = ExecutedEvents::load_binary(&bad).err().unwrap();
+        assert!(err.to_string().contains("record 16"), "{}", err);
+        assert_eq!(ExecutedEvents::recover_binary(&bad).unwrap().len(), 15);
+
+        // recover should only ignore problems with the last record
+        let mut bad = bytes.clone();
+        let index = bytes.len() / 2;
+        bad[index] ^= 0x10;
+        assert!(ExecutedEvents::recover_binary(&bad).is_err());
+
+        // the format version must match
+        let mut bad = bytes.clone();
+        bad[4] += 1;
+        let err = ExecutedEvents::load_binary(&bad).err().unwrap();
+        assert!(err.to_string().contains("version"), "{}", err);
+
+        assert!(ExecutedEvents::load_binary(b"NewGame 3\n").is_err());
     }
 
-    // TODO: probably should have a couple more tests:
-    // empty file
-    // missing file?
-    // complete garbage file
-    // malformed file
-    // maybe one with trailing garbage
+    #[test]
+    fn test_binary_truncated() {
+        let bytes = save_binary("truncated", &all_events());
+        let torn = &bytes[..bytes.len() - 3];
+        assert!(ExecutedEvents::load_binary(torn).is_err());
+        assert_eq!(ExecutedEvents::recover_binary(torn).unwrap().len(), 15);
+    }
+
+    #[test]
+    fn test_binary_journal() {
+        let path = std::path::PathBuf::from("/tmp/crippled-god-journal.bin");
+        let mut events = ExecutedEvents::new();
+        events.append(&Event::NewGame(3));
+        events.start_journal(&path, EventFormat::Binary).unwrap();
+        events.append(&Event::SetPlayer(Point::new(1, 2)));
+        events.flush().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let loaded = ExecutedEvents::load_binary(&bytes).unwrap();
+        assert_eq!(to_vec(&loaded), to_vec(&events));
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("text".parse::<EventFormat>(), Ok(EventFormat::Text));
+        assert_eq!("binary".parse::<EventFormat>(), Ok(EventFormat::Binary));
+        assert!("json".parse::<EventFormat>().is_err());
+        assert_eq!(EventFormat::Binary.to_string(), "binary");
+    }
+
+    fn drain(pending: &mut PendingEvents) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(front) = pending.front().cloned() {
+            let event = pending.pop_front();
+            assert_eq!(front, event);
+            events.push(event);
+        }
+        assert!(pending.is_empty());
+        events
+    }
+
+    #[test]
+    fn test_fifo_within_phase() {
+        let mut pending = PendingEvents::new();
+        pending.push_back(Event::SetTerrain(Point::new(1, 1), Terrain::Wall));
+        pending.push_back(Event::ResetLevel(
+            "town".to_string(),
+            Size::new(3, 3),
+            Terrain::Ground,
+        ));
+        pending.push_back(Event::SetTerrain(Point::new(2, 2), Terrain::Wall));
+        assert_eq!(pending.len(), 3);
+
+        let names: Vec<&str> = drain(&mut pending).iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["SetTerrain", "ResetLevel", "SetTerrain"]);
+    }
+
+    #[test]
+    fn test_phase_order() {
+        // Pushed in the worst possible order.
+        let mut pending = PendingEvents::new();
+        pending.push_back(Event::AdvanceTime(Time(10)));
+        pending.push_back(Event::SetPlayerReady(Time(10)));
+        pending.push_back(Event::SetPlayer(Point::new(1, 1)));
+        pending.push_back(Event::NewLevel);
+        pending.push_back(Event::SetTerrain(Point::new(1, 1), Terrain::Ground));
+        pending.push_back(Event::NewBranch);
+        pending.push_back(Event::NewGame(1));
+
+        let names: Vec<&str> = drain(&mut pending).iter().map(|e| e.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "NewBranch",
+                "NewGame",
+                "SetTerrain",
+                "NewLevel",
+                "SetPlayerReady",
+                "SetPlayer",
+                "AdvanceTime"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_later_events_can_preempt() {
+        // Events queued while processing run before events in later phases
+        // that were queued earlier.
+        let mut pending = PendingEvents::new();
+        pending.push_back(Event::NewBranch);
+        pending.push_back(Event::AdvanceTime(Time(0)));
+        assert_eq!(pending.pop_front(), Event::NewBranch);
+
+        pending.push_back(Event::NewLevel);
+        pending.push_back(Event::SetTerrain(Point::new(1, 1), Terrain::Ground));
+        assert_eq!(pending.pop_front().phase(), Phase::Level);
+
+        pending.push_back(Event::SetPlayer(Point::new(1, 1)));
+        assert_eq!(pending.pop_front(), Event::NewLevel);
+        assert_eq!(pending.pop_front().phase(), Phase::Characters);
+        assert_eq!(pending.pop_front(), Event::AdvanceTime(Time(0)));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_many_events() {
+        // This used to be quadratic.
+        let mut pending = PendingEvents::new();
+        for x in 0..200_000 {
+            pending.push_back(Event::SetTerrain(Point::new(x, 0), Terrain::Ground));
+        }
+        for x in 0..200_000 {
+            assert_eq!(
+                pending.pop_front(),
+                Event::SetTerrain(Point::new(x, 0), Terrain::Ground)
+            );
+        }
+        assert!(pending.is_empty());
+    }
 }
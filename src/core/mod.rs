@@ -1,4 +1,6 @@
 //! Items used by multiple services.
+pub mod binary;
+pub mod brand;
 pub mod event;
 pub mod events;
 pub mod point;
@@ -7,32 +9,99 @@ pub mod size;
 pub mod store;
 pub mod terrain;
 pub mod time;
+pub mod topic;
 pub mod vec2d;
 
-pub use event::Event;
-pub use events::{ExecutedEvents, PendingEvents};
+pub use brand::Brand;
+pub use event::{Event, Phase, EVENT_NAMES};
+pub use events::{EventFormat, ExecutedEvents, PendingEvents};
 pub use point::Point;
 pub use pov::POV;
 pub use size::Size;
 pub use store::*;
 pub use terrain::Terrain;
 pub use time::*;
+pub use topic::Topic;
 
 use file_scanner::Scanner;
-use std::fs::File;
+use regex::Regex;
+use std::io::{Read, Write};
 
 #[cfg(test)]
 pub use vec2d::Vec2d;
 
-pub fn next_string(scanner: &mut Scanner<File>) -> Option<String> {
-    let old = scanner.get_delim();
+/// Reads a double quoted string written with write_string.
+pub fn next_string<R: Read>(scanner: &mut Scanner<R>) -> Option<String> {
+    let old = scanner.get_delim().clone();
+    let result = next_quoted(scanner, &old);
+    scanner.set_delim(old);
+    result
+}
 
+fn next_quoted<R: Read>(scanner: &mut Scanner<R>, whitespace: &Regex) -> Option<String> {
+    // The first token is whatever separated the string from the previous
+    // token (usually a space).
     scanner.set_delim_str("\"");
-    let result = scanner.next();
+    if !scanner.next()?.trim().is_empty() {
+        return None;
+    }
+
+    // Next is the opening quote (or the opening and closing quotes if the
+    // string is empty). Escaped quotes are always preceded by a backslash so
+    // runs of quotes are at most two long.
+    let not_quote = Regex::new("[^\"]").unwrap();
+    scanner.set_delim(not_quote.clone());
+    match scanner.next()?.as_str() {
+        "\"" => (),
+        "\"\"" => return Some(String::new()),
+        _ => return None,
+    }
+
+    // Read up to each quote until we find one that isn't escaped.
+    let mut raw = String::new();
+    loop {
+        scanner.set_delim_str("\"");
+        raw += &scanner.next()?;
+        let backslashes = raw.chars().rev().take_while(|c| *c == '\\').count();
+        if backslashes % 2 == 0 {
+            scanner.set_delim(whitespace.clone());
+            return match scanner.next()?.as_str() {
+                "\"" => unescape(&raw),
+                _ => None,
+            };
+        }
 
-    scanner.set_delim(*old);
-    match scanner.next() {
-        Some(c) if c == "\"" => result,
-        _ => None,
+        scanner.set_delim(not_quote.clone());
+        match scanner.next()?.as_str() {
+            "\"" => raw.push('"'),
+            "\"\"" => {
+                raw.push('"');
+                return unescape(&raw);
+            }
+            _ => return None,
+        }
     }
 }
+
+fn unescape(raw: &str) -> Option<String> {
+    let mut text = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(c) if c == '\\' || c == '"' => text.push(c),
+                _ => return None,
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    Some(text)
+}
+
+/// Writes a double quoted string that next_string can read back. Double quotes
+/// and backslashes within the string are escaped with a backslash.
+pub fn write_string<W: Write>(w: &mut W, text: &str) -> std::io::Result<()> {
+    let text = text.replace('\\', "\\\\").replace('"', "\\\"");
+    write!(w, "\"{}\"", text)
+}
@@ -1,7 +1,8 @@
+use super::binary::{self, Decoder};
 use file_scanner::Scanner;
 use std::fmt;
 use std::fs::File;
-use std::io::{BufWriter, Result, Write};
+use std::io::{BufWriter, Read, Result, Write};
 
 /// Location within the map.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -15,7 +16,7 @@ impl Point {
         Point { x, y }
     }
 
-    pub fn from_saved(scanner: &mut Scanner<File>) -> Option<Point> {
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Point> {
         match (scanner.next_int(), scanner.next_int()) {
             (Some(x), Some(y)) => Some(Point::new(x, y)),
             _ => None,
@@ -27,9 +28,29 @@ impl Point {
         Point { x: 0, y: 0 }
     }
 
+    /// Returns the square of the distance between the two points (this avoids
+    /// floating point which is a bit nicer for replays).
+    pub fn distance2(self, rhs: Point) -> i32 {
+        let dx = self.x - rhs.x;
+        let dy = self.y - rhs.y;
+        dx * dx + dy * dy
+    }
+
     pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
         write!(w, "{} {}", self.x, self.y)
     }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Point> {
+        match (decoder.get_i32(), decoder.get_i32()) {
+            (Some(x), Some(y)) => Some(Point::new(x, y)),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        binary::put_ivarint(buf, i64::from(self.x));
+        binary::put_ivarint(buf, i64::from(self.y));
+    }
 }
 
 impl fmt::Display for Point {
@@ -6,6 +6,7 @@
 use super::*;
 use fnv::FnvHashSet;
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct POV<V, B>
 where
 	V: FnMut(Point),
@@ -291,7 +292,7 @@ impl View {
 	}
 }
 
-fn add_shallow_bump(loc: Point, active_views: &mut Vec<View>, view_index: usize) {
+fn add_shallow_bump(loc: Point, active_views: &mut [View], view_index: usize) {
 	let view = &mut active_views[view_index];
 
 	view.shallow_line.f = loc;
@@ -304,7 +305,7 @@ fn add_shallow_bump(loc: Point, active_views: &mut Vec<View>, view_index: usize)
 	}
 }
 
-fn add_steep_bump(loc: Point, active_views: &mut Vec<View>, view_index: usize) {
+fn add_steep_bump(loc: Point, active_views: &mut [View], view_index: usize) {
 	let view = &mut active_views[view_index];
 
 	view.steep_line.f = loc;
@@ -1,7 +1,8 @@
+use super::binary::{self, Decoder};
 use file_scanner::Scanner;
 use std::fmt;
 use std::fs::File;
-use std::io::{BufWriter, Result, Write};
+use std::io::{BufWriter, Read, Result, Write};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Size {
@@ -14,7 +15,7 @@ impl Size {
         Size { width, height }
     }
 
-    pub fn from_saved(scanner: &mut Scanner<File>) -> Option<Size> {
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Size> {
         match (scanner.next_int(), scanner.next_int()) {
             (Some(w), Some(h)) => Some(Size::new(w, h)),
             _ => None,
@@ -36,6 +37,18 @@ impl Size {
     pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
         write!(w, "{} {}", self.width, self.height)
     }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Size> {
+        match (decoder.get_i32(), decoder.get_i32()) {
+            (Some(w), Some(h)) => Some(Size::new(w, h)),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        binary::put_ivarint(buf, i64::from(self.width));
+        binary::put_ivarint(buf, i64::from(self.height));
+    }
 }
 
 impl fmt::Display for Size {
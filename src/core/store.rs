@@ -4,15 +4,19 @@
 //! The store encodes the entire game state and is operated upon by various components to
 //! change state as the game is played and then to render the game. Note that there are
 //! separate stores for each level.
+use super::binary::{self, Decoder};
 use super::*;
+use file_scanner::Scanner;
 use fnv::FnvHashMap;
 use fnv::FnvHashSet;
 use slog::Logger;
 use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Read, Result};
 
 /// This is used to identify an object within the game, eg an instance of an
 /// NPC, the player, a location within the map, etc.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Subject(String);
 
 impl Subject {
@@ -29,6 +33,22 @@ impl Subject {
 	pub fn new_instance(store: &mut Store, class: &str, name: &str) -> Subject {
 		Subject(store.instance_name(class, name))
 	}
+
+	pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Subject> {
+		next_string(scanner).map(Subject)
+	}
+
+	pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
+		write_string(w, &self.0)
+	}
+
+	pub fn decode(decoder: &mut Decoder) -> Option<Subject> {
+		decoder.get_str().map(Subject)
+	}
+
+	pub fn encode(&self, buf: &mut Vec<u8>) {
+		binary::put_str(buf, &self.0);
+	}
 }
 
 impl fmt::Display for Subject {
@@ -38,8 +58,8 @@ impl fmt::Display for Subject {
 }
 
 lazy_static! {
-	pub static ref LEVEL: Subject = { Subject::new_unique("level") };
-	pub static ref PLAYER: Subject = { Subject::new_unique("player") };
+	pub static ref LEVEL: Subject = Subject::new_unique("level");
+	pub static ref PLAYER: Subject = Subject::new_unique("player");
 }
 
 pub fn cell(loc: Point) -> Subject {
@@ -49,15 +69,35 @@ pub fn cell(loc: Point) -> Subject {
 /// Used to form a relation between a Subject and an Object.
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 pub enum Predicate {
+	/// The player's background, e.g. "Sapper".
+	Background,
 	Character,
+	/// Added to the damage of each of the character's attacks.
+	DamageBonus,
+	/// Set to true when a character dies.
+	Dead,
+	/// Hit points, when these reach zero the character dies.
+	HPs,
 	// Items,
 	LastSeenChar,
 	LastSeenTerrain,
 	Loc,
+	/// Hit points that the character starts out with.
+	MaxHPs,
 	Name,
+	/// Number of messages that have been added (this is set on LEVEL).
+	NumMessages,
 	Ready,
 	Size,
+	/// The species of a character, e.g. "ay".
+	Species,
 	Terrain,
+	/// The text of a message.
+	Text,
+	/// Current game time (this is set on LEVEL) or the time a message was added.
+	Time,
+	/// The topic of a message.
+	Topic,
 	Visible,
 }
 
@@ -65,6 +105,7 @@ pub enum Predicate {
 #[derive(Debug)]
 pub enum Object {
 	Bool(bool),
+	Int(i32),
 	Point(Point),
 	Ref(Subject),
 	// Refs(Vec<Subject>),
@@ -72,6 +113,7 @@ pub enum Object {
 	Str(String),
 	Terrain(Terrain),
 	Time(Time),
+	Topic(Topic),
 }
 
 impl fmt::Display for Object {
@@ -79,12 +121,14 @@ impl fmt::Display for Object {
 		match self {
 			Object::Bool(true) => write!(f, "true"),
 			Object::Bool(false) => write!(f, "false"),
+			Object::Int(v) => write!(f, "{}", v),
 			Object::Point(v) => write!(f, "{}", v),
 			Object::Ref(v) => write!(f, "{}", v),
 			Object::Size(v) => write!(f, "{}", v),
 			Object::Str(v) => write!(f, "\"{}\"", v),
 			Object::Terrain(v) => write!(f, "{}", v),
 			Object::Time(v) => write!(f, "{}", v),
+			Object::Topic(v) => write!(f, "{}", v),
 		}
 	}
 }
@@ -168,10 +212,21 @@ impl Store {
 		}
 	}
 
+	/// Removes the subject from its instance class, e.g. when an NPC dies. The
+	/// subject's predicates are left alone so that they can still be used to
+	/// describe what happened.
+	pub fn remove_instance(&mut self, event: &Event, class: &str, subject: &Subject) {
+		if let Some(inner) = self.classes.get_mut(class) {
+			if inner.remove(subject) {
+				trace!(self.logger, "removed instance"; "event" => ?event, "class" => class, "subject" => %subject);
+			}
+		}
+	}
+
 	pub fn iter_by_instance_class(
 		self: &Store,
 		class: &str,
-	) -> std::collections::hash_set::Iter<Subject> {
+	) -> std::collections::hash_set::Iter<'_, Subject> {
 		if let Some(inner) = self.classes.get(class) {
 			inner.iter()
 		} else {
@@ -191,6 +246,18 @@ impl Store {
 		}
 	}
 
+	pub fn lookup_int(&self, subject: &Subject, predicate: Predicate) -> Option<i32> {
+		if let Some(inner) = self.data.get(subject) {
+			if let Some(Object::Int(v)) = inner.get(&predicate) {
+				Some(*v)
+			} else {
+				None
+			}
+		} else {
+			None
+		}
+	}
+
 	pub fn lookup_pt(&self, subject: &Subject, predicate: Predicate) -> Option<Point> {
 		if let Some(inner) = self.data.get(subject) {
 			if let Some(Object::Point(v)) = inner.get(&predicate) {
@@ -215,6 +282,18 @@ impl Store {
 		}
 	}
 
+	pub fn lookup_str(&self, subject: &Subject, predicate: Predicate) -> Option<String> {
+		if let Some(inner) = self.data.get(subject) {
+			if let Some(Object::Str(v)) = inner.get(&predicate) {
+				Some(v.clone())
+			} else {
+				None
+			}
+		} else {
+			None
+		}
+	}
+
 	pub fn lookup_size(&self, subject: &Subject, predicate: Predicate) -> Option<Size> {
 		if let Some(inner) = self.data.get(subject) {
 			if let Some(Object::Size(v)) = inner.get(&predicate) {
@@ -251,6 +330,18 @@ impl Store {
 		}
 	}
 
+	pub fn lookup_topic(&self, subject: &Subject, predicate: Predicate) -> Option<Topic> {
+		if let Some(inner) = self.data.get(subject) {
+			if let Some(Object::Topic(v)) = inner.get(&predicate) {
+				Some(*v)
+			} else {
+				None
+			}
+		} else {
+			None
+		}
+	}
+
 	fn instance_name(&mut self, class: &str, base: &str) -> String {
 		let name = format!("{}-{}", base, self.count);
 		self.count += 1;
@@ -1,7 +1,8 @@
+use super::binary::{self, Decoder};
 use file_scanner::Scanner;
 use std::fmt;
 use std::fs::File;
-use std::io::{BufWriter, Result, Write};
+use std::io::{BufWriter, Read, Result, Write};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Terrain {
@@ -12,7 +13,7 @@ pub enum Terrain {
 }
 
 impl Terrain {
-    pub fn from_saved(scanner: &mut Scanner<File>) -> Option<Terrain> {
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Terrain> {
         if let Some(name) = scanner.next() {
             match name.as_str() {
                 "DeepWater" => Some(Terrain::DeepWater),
@@ -34,6 +35,26 @@ impl Terrain {
             Terrain::Wall => write!(w, "Wall"),
         }
     }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Terrain> {
+        match decoder.get_u8() {
+            Some(0) => Some(Terrain::DeepWater),
+            Some(1) => Some(Terrain::Ground),
+            Some(2) => Some(Terrain::ShallowWater),
+            Some(3) => Some(Terrain::Wall),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let tag = match self {
+            Terrain::DeepWater => 0,
+            Terrain::Ground => 1,
+            Terrain::ShallowWater => 2,
+            Terrain::Wall => 3,
+        };
+        binary::put_u8(buf, tag);
+    }
 }
 
 impl fmt::Display for Terrain {
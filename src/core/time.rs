@@ -1,8 +1,8 @@
+use super::binary::{self, Decoder};
 use file_scanner::Scanner;
 use std::fmt;
 use std::fs::File;
-use std::i32; // for MAX
-use std::io::{BufWriter, Result, Write};
+use std::io::{BufWriter, Read, Result, Write};
 use std::ops::{Add, AddAssign};
 
 /// Time at which a character (or item) will do something.
@@ -16,34 +16,38 @@ pub struct Duration(pub i32);
 
 pub const INFINITE_TIME: Time = Time(i32::MAX);
 
-// /// Used to re-schedule an NPC when they decide to do nothing.
-// pub const NO_OP_DURATION: Duration = Duration(10);
+/// Used to re-schedule an NPC when they decide to do nothing.
+pub const NO_OP_DURATION: Duration = Duration(10);
 
 // pub const INFINITE_DURATION: Duration = Duration(i32::MAX);
 
-/// Smallest unit of time: a tenth of a second.
+// Smallest unit of time: a tenth of a second.
 // pub const TICK: Duration = Duration(1);
 
 impl Time {
-    // pub fn zero() -> Time {
-    // 	Time(0)
-    // }
+    pub fn zero() -> Time {
+        Time(0)
+    }
 
     pub fn from_secs(secs: f32) -> Time {
-        Time((secs * 10.0) as i32)
+        Time((secs * 10.0).round() as i32)
     }
 
-    pub fn from_saved(scanner: &mut Scanner<File>) -> Option<Time> {
-        if let Some(raw) = scanner.next_int() {
-            Some(Time(raw))
-        } else {
-            None
-        }
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Time> {
+        scanner.next_int().map(Time)
     }
 
     pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
         write!(w, "{}", self.0)
     }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Time> {
+        decoder.get_i32().map(Time)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        binary::put_ivarint(buf, i64::from(self.0));
+    }
 }
 
 impl Add<Duration> for Time {
@@ -62,7 +66,7 @@ impl AddAssign<Duration> for Time {
 
 impl Duration {
     pub fn from_secs(secs: f32) -> Duration {
-        Duration((secs * 10.0) as i32)
+        Duration((secs * 10.0).round() as i32)
     }
 
     // 	pub fn percent(self, p: f64) -> Duration {
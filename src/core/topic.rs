@@ -0,0 +1,121 @@
+use super::binary::{self, Decoder};
+use file_scanner::Scanner;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Read, Result, Write};
+
+/// Used with messages, e.g. so that the UI can color messages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Topic {
+    /// An operation could not be completed.
+    Error,
+
+    /// Something that doesn't affect the game.
+    NonGamePlay,
+
+    /// NPC was damaged (but not by the player).
+    NpcIsDamaged, // TODO: might want to have a separate Topic for player allies
+
+    /// NPC was attacked but not damaged (but not by the player).
+    NpcIsNotDamaged,
+
+    /// The player has caused damage.
+    PlayerDidDamage,
+
+    /// The player attacked but did no damage.
+    PlayerDidNoDamage,
+
+    /// The player has taken damage.
+    PlayerIsDamaged,
+
+    /// The player was attacked but took no damage.
+    PlayerIsNotDamaged,
+
+    /// The player will operate less well.
+    PlayerIsImpaired, // TODO: probably also want a PlayerEnchanced
+
+    /// The player is at risk of taking damage.
+    PlayerIsThreatened,
+
+    /// An operation was not completely successful.
+    Warning,
+}
+
+impl Topic {
+    pub fn from_saved<R: Read>(scanner: &mut Scanner<R>) -> Option<Topic> {
+        if let Some(name) = scanner.next() {
+            match name.as_str() {
+                "Error" => Some(Topic::Error),
+                "NonGamePlay" => Some(Topic::NonGamePlay),
+                "NpcIsDamaged" => Some(Topic::NpcIsDamaged),
+                "NpcIsNotDamaged" => Some(Topic::NpcIsNotDamaged),
+                "PlayerDidDamage" => Some(Topic::PlayerDidDamage),
+                "PlayerDidNoDamage" => Some(Topic::PlayerDidNoDamage),
+                "PlayerIsDamaged" => Some(Topic::PlayerIsDamaged),
+                "PlayerIsNotDamaged" => Some(Topic::PlayerIsNotDamaged),
+                "PlayerIsImpaired" => Some(Topic::PlayerIsImpaired),
+                "PlayerIsThreatened" => Some(Topic::PlayerIsThreatened),
+                "Warning" => Some(Topic::Warning),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    pub fn write(&self, w: &mut BufWriter<File>) -> Result<()> {
+        match self {
+            Topic::Error => write!(w, "Error"),
+            Topic::NonGamePlay => write!(w, "NonGamePlay"),
+            Topic::NpcIsDamaged => write!(w, "NpcIsDamaged"),
+            Topic::NpcIsNotDamaged => write!(w, "NpcIsNotDamaged"),
+            Topic::PlayerDidDamage => write!(w, "PlayerDidDamage"),
+            Topic::PlayerDidNoDamage => write!(w, "PlayerDidNoDamage"),
+            Topic::PlayerIsDamaged => write!(w, "PlayerIsDamaged"),
+            Topic::PlayerIsNotDamaged => write!(w, "PlayerIsNotDamaged"),
+            Topic::PlayerIsImpaired => write!(w, "PlayerIsImpaired"),
+            Topic::PlayerIsThreatened => write!(w, "PlayerIsThreatened"),
+            Topic::Warning => write!(w, "Warning"),
+        }
+    }
+
+    pub fn decode(decoder: &mut Decoder) -> Option<Topic> {
+        match decoder.get_u8() {
+            Some(0) => Some(Topic::Error),
+            Some(1) => Some(Topic::NonGamePlay),
+            Some(2) => Some(Topic::NpcIsDamaged),
+            Some(3) => Some(Topic::NpcIsNotDamaged),
+            Some(4) => Some(Topic::PlayerDidDamage),
+            Some(5) => Some(Topic::PlayerDidNoDamage),
+            Some(6) => Some(Topic::PlayerIsDamaged),
+            Some(7) => Some(Topic::PlayerIsNotDamaged),
+            Some(8) => Some(Topic::PlayerIsImpaired),
+            Some(9) => Some(Topic::PlayerIsThreatened),
+            Some(10) => Some(Topic::Warning),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let tag = match self {
+            Topic::Error => 0,
+            Topic::NonGamePlay => 1,
+            Topic::NpcIsDamaged => 2,
+            Topic::NpcIsNotDamaged => 3,
+            Topic::PlayerDidDamage => 4,
+            Topic::PlayerDidNoDamage => 5,
+            Topic::PlayerIsDamaged => 6,
+            Topic::PlayerIsNotDamaged => 7,
+            Topic::PlayerIsImpaired => 8,
+            Topic::PlayerIsThreatened => 9,
+            Topic::Warning => 10,
+        };
+        binary::put_u8(buf, tag);
+    }
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
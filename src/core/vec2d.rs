@@ -50,7 +50,7 @@ impl<T: Clone> Vec2d<T> {
 	// 	&mut self.elements[index as usize]
 	// }
 
-	pub fn iter(&self) -> Vec2dIter<T> {
+	pub fn iter(&self) -> Vec2dIter<'_, T> {
 		Vec2dIter {
 			index: 0,
 			vector: self,
@@ -1,14 +1,29 @@
 use super::core::*;
 use super::player::*;
+use super::service::*;
 use fnv::FnvHashMap;
+use rand::rngs::SmallRng;
 
-pub fn new_level(store: &mut Store, event: &Event) {
+/// How far characters can see.
+const LOS_RADIUS: i32 = 10; // TODO: depends on race?
+
+fn new_level(store: &mut Store, event: &Event) {
     store.insert(
         event,
         &LEVEL,
         Predicate::Name,
         Object::Str("uninitialized".to_string()),
     );
+
+    let subject = Subject::new_unique("dummy-cell");
+    store.insert(event, &subject, Predicate::Visible, Object::Bool(false));
+}
+
+/// Returns the time that the game last advanced to.
+pub fn current_time(store: &Store) -> Time {
+    store
+        .lookup_time(&LEVEL, Predicate::Time)
+        .unwrap_or_else(Time::zero)
 }
 
 pub fn get_level_size(store: &Store) -> Size {
@@ -29,15 +44,31 @@ pub fn get_level_terrain(store: &Store, loc: Point) -> Terrain {
         .unwrap_or_else(|| panic!("Couldn't find terrain for {:?}", subject))
 }
 
-pub fn on_level_event(store: &mut Store, event: &Event, _pending: &mut PendingEvents) {
+/// Maintains the level's name, size, and terrain.
+pub struct LevelService;
+
+impl Service for LevelService {
+    fn on_event(
+        &mut self,
+        store: &mut Store,
+        _rng: &mut SmallRng,
+        event: &Event,
+        pending: &mut PendingEvents,
+    ) {
+        on_level_event(store, event, pending);
+    }
+}
+
+fn on_level_event(store: &mut Store, event: &Event, _pending: &mut PendingEvents) {
     match event {
+        Event::AdvanceTime(time) => {
+            store.insert(event, &LEVEL, Predicate::Time, Object::Time(*time));
+        }
+        Event::NewGame(_) => new_level(store, event),
         Event::ResetLevel(name, size, terrain) => {
             store.insert(event, &LEVEL, Predicate::Name, Object::Str(name.clone()));
             store.insert(event, &LEVEL, Predicate::Size, Object::Size(*size));
 
-            let subject = Subject::new_unique("dummy-cell"); // TODO: should probaby handle this with a NewGame event
-            store.insert(event, &subject, Predicate::Visible, Object::Bool(false));
-
             for y in 0..size.height {
                 for x in 0..size.width {
                     let subject = cell(Point::new(x, y));
@@ -78,6 +109,28 @@ pub fn get_last_seen(store: &mut Store, event: &Event, screen_size: Size) -> Vec
     //self.invariant();		// TODO: probably want something like this somewhere
 }
 
+/// Returns true if a character at start can see target.
+pub fn is_visible(store: &Store, start: Point, target: Point) -> bool {
+    if start.distance2(target) > LOS_RADIUS * LOS_RADIUS {
+        return false;
+    }
+
+    let mut visible = false;
+    let mut pov = POV {
+        start,
+        size: get_level_size(store),
+        radius: LOS_RADIUS,
+        visible_tile: |loc| {
+            if loc == target {
+                visible = true;
+            }
+        },
+        blocks_los: |loc| matches!(get_level_terrain(store, loc), Terrain::Wall),
+    };
+    pov.visit();
+    visible
+}
+
 // Updates the tiles that are within the player's LOS.
 fn update_tiles(store: &mut Store, event: &Event) {
     // The borrow checker won't allow us to grab a mutable reference to tiles in one closure and
@@ -89,7 +142,7 @@ fn update_tiles(store: &mut Store, event: &Event) {
     let mut pov = POV {
         start: player_loc,
         size,
-        radius: 10, // TODO: depends on race?
+        radius: LOS_RADIUS,
         visible_tile: |loc| {
             let terrain = get_level_terrain(store, loc);
             visible.insert(loc, terrain);
@@ -106,13 +159,8 @@ fn update_tiles(store: &mut Store, event: &Event) {
             let loc = Point::new(x, y);
             let subject = cell(loc);
             if let Some(terrain) = visible.get(&loc) {
-                if loc == player_loc {
-                    store.insert(
-                        event,
-                        &subject,
-                        Predicate::LastSeenChar,
-                        Object::Ref(PLAYER.clone()),
-                    );
+                if let Some(ch) = store.lookup_ref(&subject, Predicate::Character) {
+                    store.insert(event, &subject, Predicate::LastSeenChar, Object::Ref(ch));
                 } else {
                     store.remove(event, &subject, Predicate::LastSeenChar);
                 };
@@ -131,8 +179,10 @@ fn update_tiles(store: &mut Store, event: &Event) {
     }
 }
 
-// Returns the subset of tiles that are rendered on the screen.
-fn screen_tiles(store: &Store, screen_size: Size) -> Vec<(Point, Subject)> {
+/// Returns the subset of tiles that are rendered on the screen. Unlike
+/// get_last_seen this doesn't update the store so it can be used to redraw the
+/// screen, e.g. after the terminal is resized.
+pub fn screen_tiles(store: &Store, screen_size: Size) -> Vec<(Point, Subject)> {
     let mut tiles = Vec::new();
     let player_loc = player_loc(store);
     let start_x = player_loc.x - screen_size.width / 2;
@@ -1,18 +1,186 @@
 mod main_branch;
 
+use super::character::*;
 use super::core::*;
+use super::level::*;
+use super::service::*;
+use super::species::*;
+use fnv::FnvHashSet;
+use rand::rngs::SmallRng;
+use std::path::Path;
+use std::rc::Rc;
 
-pub fn level_gen_ready_time(_store: &Store) -> Time {
-	INFINITE_TIME
+/// Number of NPCs of a species to add to each new level.
+#[derive(Clone, Debug)]
+pub struct Spawn {
+	pub species: String,
+	pub count: i32,
 }
 
-pub fn on_level_gen_event(_store: &mut Store, event: &Event, pending: &mut PendingEvents) {
-	if let Event::NewBranch = event {
-		// TODO: probably want some sort of invariant check here
-		// eg: that perimeter is some sort of permanent wall
-		// and open areas exist
-		// and maybe that all open areas are reachable
-		main_branch::new(pending);
-		pending.push_back(Event::NewLevel);
+/// Queues up the events used to build new levels.
+pub struct LevelGenService {
+	species: Rc<SpeciesTable>,
+	spawns: Vec<Spawn>,
+}
+
+impl LevelGenService {
+	/// Panics if a spawn uses a species that isn't in the table.
+	pub fn new(species: Rc<SpeciesTable>, spawns: Vec<Spawn>) -> LevelGenService {
+		for spawn in spawns.iter() {
+			assert!(
+				species.get(&spawn.species).is_some(),
+				"unknown species {}",
+				spawn.species
+			);
+		}
+		LevelGenService { species, spawns }
+	}
+}
+
+impl Service for LevelGenService {
+	fn on_event(
+		&mut self,
+		store: &mut Store,
+		rng: &mut SmallRng,
+		event: &Event,
+		pending: &mut PendingEvents,
+	) {
+		on_level_gen_event(store, &self.species, rng, &self.spawns, event, pending);
+	}
+}
+
+/// Loads the NPCs to add to each new level from a data file (see data/spawns.txt).
+pub fn load_spawns(path: &Path, species: &SpeciesTable) -> Result<Vec<Spawn>, String> {
+	let text = std::fs::read_to_string(path)
+		.map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+	parse_spawns(&text, species).map_err(|err| format!("{}:{}", path.display(), err))
+}
+
+/// Errors are prefixed with the line number, e.g. "3: unknown species 'dragon'".
+pub fn parse_spawns(text: &str, species: &SpeciesTable) -> Result<Vec<Spawn>, String> {
+	let mut spawns = Vec::new();
+	for (index, line) in text.lines().enumerate() {
+		let line_num = index + 1;
+		let line = line.split('#').next().unwrap();
+		let tokens: Vec<&str> = line.split_whitespace().collect();
+		match tokens[..] {
+			[] => (),
+			["spawn", name, count] => {
+				if species.get(name).is_none() {
+					return Err(format!("{}: unknown species '{}'", line_num, name));
+				}
+				let count = match count.parse() {
+					Ok(count) if count > 0 => count,
+					_ => {
+						return Err(format!(
+							"{}: count should be a positive integer but found '{}'",
+							line_num, count
+						))
+					}
+				};
+				spawns.push(Spawn {
+					species: name.to_string(),
+					count,
+				});
+			}
+			_ => return Err(format!("{}: expected 'spawn SPECIES COUNT'", line_num)),
+		}
+	}
+	Ok(spawns)
+}
+
+/// Used by the unit tests to load data/spawns.txt.
+#[cfg(test)]
+pub fn test_spawns() -> Vec<Spawn> {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/spawns.txt");
+	load_spawns(&path, &test_species()).unwrap()
+}
+
+fn on_level_gen_event(
+	store: &mut Store,
+	species: &SpeciesTable,
+	rng: &mut SmallRng,
+	spawns: &[Spawn],
+	event: &Event,
+	pending: &mut PendingEvents,
+) {
+	match event {
+		Event::NewBranch => {
+			// TODO: probably want some sort of invariant check here
+			// eg: that perimeter is some sort of permanent wall
+			// and open areas exist
+			// and maybe that all open areas are reachable
+			main_branch::new(pending);
+			pending.push_back(Event::NewLevel);
+			pending.push_back(Event::PopulateLevel);
+		}
+		Event::PopulateLevel => populate(store, species, rng, spawns, pending),
+		_ => (),
+	}
+}
+
+fn populate(
+	store: &Store,
+	species: &SpeciesTable,
+	rng: &mut SmallRng,
+	spawns: &[Spawn],
+	pending: &mut PendingEvents,
+) {
+	let ready = current_time(store) + Duration::from_secs(1.1);
+
+	// The NewNPC events won't execute until we return so we need to keep track of
+	// the locations we've already used.
+	let mut used = FnvHashSet::default();
+	for spawn in spawns {
+		let hps = species.get(&spawn.species).unwrap().max_hps;
+		for _ in 0..spawn.count {
+			if let Some(loc) = find_unused_loc(store, rng, &used) {
+				used.insert(loc);
+				pending.push_back(Event::NewNPC(loc, spawn.species.clone(), hps, ready));
+			}
+		}
+	}
+}
+
+fn find_unused_loc(store: &Store, rng: &mut SmallRng, used: &FnvHashSet<Point>) -> Option<Point> {
+	for _ in 0..100 {
+		match find_char_loc(store, rng) {
+			Some(loc) if !used.contains(&loc) => return Some(loc),
+			Some(_) => (),
+			None => return None,
+		}
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_data_file() {
+		let spawns = test_spawns();
+		let names: Vec<&str> = spawns.iter().map(|spawn| spawn.species.as_str()).collect();
+		assert_eq!(names, vec!["ay", "bhederin"]);
+		assert!(spawns.iter().all(|spawn| spawn.count == 5));
+	}
+
+	#[test]
+	fn test_errors() {
+		let species = test_species();
+		let parse_err = |text| parse_spawns(text, &species).err().unwrap();
+		assert_eq!(
+			parse_err("spawn ay 5\nspawn dragon 1\n"),
+			"2: unknown species 'dragon'"
+		);
+		assert_eq!(
+			parse_err("spawn ay five\n"),
+			"1: count should be a positive integer but found 'five'"
+		);
+		assert_eq!(
+			parse_err("spawn ay 0\n"),
+			"1: count should be a positive integer but found '0'"
+		);
+		assert_eq!(parse_err("\nay 5\n"), "2: expected 'spawn SPECIES COUNT'");
 	}
 }
@@ -0,0 +1,9 @@
+//! The parts of the game that are shared between the game itself and tools
+//! like eventlog.
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate slog;
+
+pub mod core;
+pub mod persist;
@@ -1,53 +1,276 @@
 #[macro_use]
-extern crate lazy_static;
-#[macro_use]
 extern crate slog;
 // extern crate slog_async;
 // extern crate slog_term;
 
 mod character;
-mod core;
+mod combat;
 mod level;
 mod level_generator;
+mod messages;
 mod npc;
 mod player;
+#[cfg(test)]
+mod playtest;
+mod service;
+mod species;
 mod terminal;
 
+use crippled_god::{core, persist};
+
 use crate::core::*;
+use character::check_occupancy;
+use combat::CombatService;
 use level::*;
 use level_generator::*;
+use messages::MessageService;
+use npc::*;
+use persist::*;
 use player::*;
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
+use service::*;
 use sloggers::Build;
+use species::SpeciesTable;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str::FromStr;
+use structopt::StructOpt;
 use terminal::*;
 
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Crippled God",
+    about = "Rogue-like based on the Malazan Books of the Fallen."
+)]
+struct Options {
+    /// Seed for the random number generator, defaults to a seed based on the current time
+    /// (ignored when replaying)
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+
+    /// One of critical, error, warning, info, debug, or trace
+    #[structopt(long = "log-level", default_value = "debug", parse(try_from_str = parse_severity))]
+    log_level: sloggers::types::Severity,
+
+    /// Where log messages are written to (the file is truncated at startup)
+    #[structopt(
+        long = "log-path",
+        default_value = "crippled-god.log",
+        parse(from_os_str)
+    )]
+    log_path: PathBuf,
+
+    /// Where the game is saved to (when the game exits or with control-s)
+    #[structopt(
+        long = "save-path",
+        default_value = "crippled-god-events.txt",
+        parse(from_os_str)
+    )]
+    save_path: PathBuf,
+
+    /// Events are written here as the game is played so that crashed games can be
+    /// recovered (the file is removed when the game exits normally)
+    #[structopt(
+        long = "journal-path",
+        default_value = "crippled-god-journal.txt",
+        parse(from_os_str)
+    )]
+    journal_path: PathBuf,
+
+    /// Events file to replay before continuing the game
+    #[structopt(long = "replay", parse(from_os_str))]
+    replay: Option<PathBuf>,
+
+    /// Format used for the saved game and journal, either text or binary (the
+    /// format is detected when loading)
+    #[structopt(long = "event-format", default_value = "text")]
+    event_format: EventFormat,
+
+    /// Data file with the species used by the game
+    #[structopt(
+        long = "species-path",
+        default_value = "data/species.txt",
+        parse(from_os_str)
+    )]
+    species_path: PathBuf,
+
+    /// Data file with the NPCs added to each new level
+    #[structopt(
+        long = "spawns-path",
+        default_value = "data/spawns.txt",
+        parse(from_os_str)
+    )]
+    spawns_path: PathBuf,
+
+    /// Data file with the key bindings
+    #[structopt(
+        long = "keys-path",
+        default_value = "data/keys.txt",
+        parse(from_os_str)
+    )]
+    keys_path: PathBuf,
+
+    /// Reads keys from this file instead of the keyboard, e.g. to reproduce a
+    /// bug (the game exits when the script runs out)
+    #[structopt(long = "script", parse(from_os_str))]
+    script: Option<PathBuf>,
+
+    /// Converts a text events file to binary (or binary to text) and exits
+    #[structopt(
+        long = "convert",
+        value_names = &["INPUT", "OUTPUT"],
+        number_of_values = 2,
+        parse(from_os_str)
+    )]
+    convert: Vec<PathBuf>,
+}
+
+fn parse_severity(text: &str) -> Result<sloggers::types::Severity, String> {
+    sloggers::types::Severity::from_str(text)
+        .map_err(|_| "should be critical, error, warning, info, debug, or trace".to_string())
+}
+
+fn validate_options(options: &Options) -> Result<(), String> {
+    if options.log_path == options.save_path {
+        return Err("--log-path and --save-path must be different".to_string());
+    }
+    if options.journal_path == options.log_path || options.journal_path == options.save_path {
+        return Err("--journal-path must be different from --log-path and --save-path".to_string());
+    }
+    if let [input, output] = &options.convert[..] {
+        if !input.is_file() {
+            return Err(format!("--convert file {} doesn't exist", input.display()));
+        }
+        if input == output {
+            return Err("--convert input and output must be different".to_string());
+        }
+    }
+    if let Some(path) = &options.replay {
+        if !path.is_file() {
+            return Err(format!("--replay file {} doesn't exist", path.display()));
+        }
+        if *path == options.save_path {
+            // We'd overwrite the events we're replaying when we exit.
+            return Err("--replay and --save-path must be different".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Used when the player picks a file to replay from the title screen.
+fn validate_replay(path: &Path, options: &Options) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("{} doesn't exist", path.display()));
+    }
+    if path == options.save_path || path == options.journal_path {
+        // We'd overwrite the events we're replaying.
+        return Err(format!(
+            "{} is the saved game, resume it instead",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
 fn main() {
-    // let severity = match sloggers::types::Severity::from_str(&options.log_level) {
-    let severity = match sloggers::types::Severity::from_str("debug") {
-        Ok(l) => l,
-        Err(_) => {
-            eprintln!("--log-level should be critical, error, warning, info, debug, or trace");
+    let options = Options::from_args();
+    if let Err(err) = validate_options(&options) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    if let [input, output] = &options.convert[..] {
+        match convert_game(input, output) {
+            Ok(format) => println!("wrote {} as {}", output.display(), format),
+            Err(err) => {
+                eprintln!("couldn't convert {}: {}", input.display(), err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let species = match SpeciesTable::load(&options.species_path) {
+        Ok(species) => Rc::new(species),
+        Err(err) => {
+            eprintln!("{}", err);
             std::process::exit(1);
         }
     };
+    if species.get(PLAYER_SPECIES).is_none() {
+        eprintln!(
+            "{} doesn't define the {} species",
+            options.species_path.display(),
+            PLAYER_SPECIES
+        );
+        std::process::exit(1);
+    }
+    if species.playable().is_empty() {
+        eprintln!(
+            "{} doesn't define any Playable species",
+            options.species_path.display()
+        );
+        std::process::exit(1);
+    }
+    let spawns = match load_spawns(&options.spawns_path, &species) {
+        Ok(spawns) => spawns,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let keys = match KeyBindings::load(&options.keys_path) {
+        Ok(keys) => keys,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let input: Box<dyn InputSource> = match &options.script {
+        Some(path) => match ScriptedInput::load(path) {
+            Ok(script) => Box::new(script),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(InputReader::new()),
+    };
+
+    let seed = options.seed.unwrap_or_else(|| {
+        let now = chrono::Local::now();
+        now.timestamp_nanos_opt().unwrap_or_default() as u64
+    });
 
     // "event" => event			uses slog::Value trait (so that output is structured)
     // "event" => %event		uses Display trait
     // "event" => ?event		uses Debug trait
-    let path = std::path::Path::new("crippled-god.log");
-    let mut builder = sloggers::file::FileLoggerBuilder::new(path);
+    let mut builder = sloggers::file::FileLoggerBuilder::new(&options.log_path);
     builder.format(sloggers::types::Format::Compact);
     builder.overflow_strategy(sloggers::types::OverflowStrategy::Block); // TODO: logging is async which is kinda lame
     builder.source_location(sloggers::types::SourceLocation::None);
-    builder.level(severity);
+    builder.level(options.log_level);
     builder.truncate();
     let root_logger = builder.build().unwrap();
 
     let local = chrono::Local::now();
-    info!(root_logger, "started up"; "on" => local.to_rfc2822(), "version" => env!("CARGO_PKG_VERSION"));
-    //	info!(root_logger, "started up"; "seed" => options.seed, "on" => local.to_rfc2822());
+    info!(root_logger, "started up"; "seed" => seed, "on" => local.to_rfc2822(), "version" => env!("CARGO_PKG_VERSION"));
+
+    // The seed is recorded in the NewGame event so replayed games wind up
+    // with the same RNG state as the original game.
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut services = new_services(&species, spawns);
+    let replayed = options.replay.as_ref().map(|path| {
+        info!(root_logger, "replaying"; "path" => %path.display());
+        let events = load_game(path);
+        match resume_game(&root_logger, events, &species, &mut services, &mut rng) {
+            Ok(game) => game,
+            Err(err) => {
+                error!(root_logger, "replay failed"; "error" => %err);
+                eprintln!("couldn't load {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    });
 
     // It would be kind of nice to package all of this up into some
     // sort of Game struct but that gets hairy because we'd have a
@@ -55,23 +278,31 @@ fn main() {
     // when we call methods). Also using a Game struct makes dependencies
     // very fuzzy, e.g. if a function takes a mut Game reference then
     // there is no good way to tell what will actually be changed.
-    let mut store = Store::new(&root_logger);
-    let mut executed = ExecutedEvents::new();
-    let mut terminal = Terminal::new(&root_logger);
-
-    let event = Event::NewBranch;
-    new_level(&mut store, &event);
-    new_player(&mut store);
-
-    // Note that we can replay games but once they have been
-    // replayed the original game and the replayed game will
-    // start to diverge because of the RNG (normally the RNG
-    // is used to determine actions but that doesn't happen
-    // during replay).
-    let mut pending = PendingEvents::new();
-    pending.push_back(event);
+    let mut terminal = Terminal::new(&root_logger, species.clone(), keys, input);
+    let (mut store, mut executed, mut pending) = match replayed {
+        Some(game) => game,
+        None => match start_game(
+            &root_logger,
+            &options,
+            &mut terminal,
+            &species,
+            &mut services,
+            &mut rng,
+            seed,
+        ) {
+            Some(game) => game,
+            None => return,
+        },
+    };
 
-    let mut rng = SmallRng::seed_from_u64(2); // TODO: get the seed from the command line
+    if let Err(err) = executed.start_journal(&options.journal_path, options.event_format) {
+        eprintln!(
+            "couldn't create {}: {}",
+            options.journal_path.display(),
+            err
+        );
+        std::process::exit(1);
+    }
 
     loop {
         // Handle all the events that are queued up.
@@ -80,18 +311,243 @@ fn main() {
             &mut pending,
             &mut executed,
             &mut store,
+            &mut services,
             &mut terminal,
             &mut rng,
         ) {
             TerminalEventResult::NotRunning => break,
+            TerminalEventResult::SaveGame => {
+                match save_game(&options.save_path, &executed, options.event_format) {
+                    Ok(_) => terminal.set_status("Saved game."),
+                    Err(err) => terminal.set_status(&format!("Failed to save game: {}.", err)),
+                }
+            }
             TerminalEventResult::Running => (),
         }
 
+        // All the events have been processed so this is a good time to make
+        // sure that they are in the journal.
+        if let Err(err) = executed.flush() {
+            warn!(root_logger, "couldn't write journal"; "error" => %err);
+        }
+
         // Once all the services have processed figure out which service will be
         // ready next and queue up an event to advance time to that point.
-        let time = find_next_scheduled(&store);
+        let time = services.ready_time(&store);
         pending.push_back(Event::AdvanceTime(time));
     }
+
+    if player_is_dead(&store) {
+        info!(root_logger, "player died");
+        delete_saved_game(&options.save_path);
+        delete_saved_game(&options.journal_path);
+    } else if let Err(err) = save_game(&options.save_path, &executed, options.event_format) {
+        // Leave the journal around so that the game can still be recovered.
+        eprintln!("couldn't save {}: {}", options.save_path.display(), err);
+    } else {
+        delete_saved_game(&options.journal_path);
+    }
+}
+
+fn new_game(
+    root_logger: &slog::Logger,
+    seed: u64,
+    character: Character,
+) -> (Store, ExecutedEvents, PendingEvents) {
+    let mut pending = PendingEvents::new();
+    pending.push_back(Event::NewGame(seed));
+    pending.push_back(Event::NewCharacter(
+        character.name,
+        character.species,
+        character.background,
+    ));
+    pending.push_back(Event::NewBranch);
+    (Store::new(root_logger), ExecutedEvents::new(), pending)
+}
+
+/// Shows the title screen until the player starts a new game or one is loaded.
+/// Returns None if the player quit.
+fn start_game(
+    root_logger: &slog::Logger,
+    options: &Options,
+    terminal: &mut Terminal,
+    species: &SpeciesTable,
+    services: &mut Services,
+    rng: &mut SmallRng,
+    seed: u64,
+) -> Option<(Store, ExecutedEvents, PendingEvents)> {
+    let mut error: Option<String> = None;
+    loop {
+        // If the journal is still around then the last game didn't exit normally.
+        let menu = MenuOptions {
+            can_recover: has_saved_game(&options.journal_path),
+            can_resume: has_saved_game(&options.save_path),
+        };
+        let (path, events) = match terminal.main_menu(&menu, error.as_deref()) {
+            MenuChoice::NewGame(character) => return Some(new_game(root_logger, seed, character)),
+            MenuChoice::Recover => (
+                options.journal_path.clone(),
+                recover_game(&options.journal_path),
+            ),
+            MenuChoice::Resume => (options.save_path.clone(), load_game(&options.save_path)),
+            MenuChoice::Replay(path) => {
+                if let Err(err) = validate_replay(&path, options) {
+                    error = Some(err);
+                    continue;
+                }
+                let events = load_game(&path);
+                (path, events)
+            }
+            MenuChoice::Quit => return None,
+        };
+
+        info!(root_logger, "resuming"; "path" => %path.display());
+        match resume_game(root_logger, events, species, services, rng) {
+            Ok(game) => return Some(game),
+            Err(err) => {
+                error!(root_logger, "resume failed"; "error" => %err);
+                error = Some(format!("couldn't load {}: {}", path.display(), err));
+            }
+        }
+    }
+}
+
+/// Services are called in the order they are registered here.
+fn new_services(species: &Rc<SpeciesTable>, spawns: Vec<Spawn>) -> Services {
+    let mut services = Services::new();
+    services.register(Box::new(LevelService));
+    services.register(Box::new(LevelGenService::new(species.clone(), spawns)));
+    services.register(Box::new(CombatService));
+    services.register(Box::new(MessageService));
+    services.register(Box::new(PlayerService::new(species.clone())));
+    services.register(Box::new(NPCService::new(species.clone())));
+    services
+}
+
+fn resume_game(
+    root_logger: &slog::Logger,
+    events: std::io::Result<ExecutedEvents>,
+    species: &SpeciesTable,
+    services: &mut Services,
+    rng: &mut SmallRng,
+) -> Result<(Store, ExecutedEvents, PendingEvents), String> {
+    let events = events.map_err(|err| err.to_string())?;
+    check_saved_data(&events, species)?;
+    let mut store = Store::new(root_logger);
+    let mut executed = ExecutedEvents::new();
+    let pending = replay(
+        root_logger,
+        &events,
+        &mut executed,
+        &mut store,
+        services,
+        rng,
+    )
+    .map_err(|err| format!("replay diverged: {}", err))?;
+    Ok((store, executed, pending))
+}
+
+/// The services assume that the species and backgrounds they're given exist
+/// so saved games made with different data files are rejected up front.
+fn check_saved_data(events: &ExecutedEvents, species: &SpeciesTable) -> Result<(), String> {
+    for (index, event) in events.iter().enumerate() {
+        let (name, background) = match event {
+            Event::NewCharacter(_, name, background) => (name, Some(background)),
+            Event::NewNPC(_, name, _, _) => (name, None),
+            _ => continue,
+        };
+        if species.get(name).is_none() {
+            return Err(format!("event {} uses unknown species {}", index, name));
+        }
+        if let Some(background) = background {
+            if find_background(background).is_none() {
+                return Err(format!(
+                    "event {} uses unknown background {}",
+                    index, background
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds the store by running the services over events from a saved game.
+/// Services normally respond to events by queuing up new events but, when
+/// replaying, those will already be in the saved events. So instead of
+/// executing them we check that they do show up in the saved events: if
+/// they don't the replay has diverged from the original game (e.g. because
+/// a service used the RNG outside of an event) and an error is returned.
+/// The only other events allowed are the player's actions (which the
+/// terminal queues up once the services have responded to AdvanceTime).
+/// Otherwise the events that were queued up but not yet executed when the
+/// saved events end are returned so that the game can be continued.
+fn replay(
+    root_logger: &slog::Logger,
+    events: &ExecutedEvents,
+    executed: &mut ExecutedEvents,
+    store: &mut Store,
+    services: &mut Services,
+    rng: &mut SmallRng,
+) -> Result<PendingEvents, String> {
+    let mut terminal = Terminal::new_headless(root_logger);
+    let mut expected = PendingEvents::new();
+
+    // The terminal queues up the player's actions after the services have
+    // responded to AdvanceTime so those responses have to execute first.
+    let mut players_turn = false;
+    let mut before_player: usize = 0;
+    for (index, event) in events.iter().enumerate() {
+        debug!(root_logger, "replaying"; "event" => %event);
+        if let Event::AdvanceTime(time) = event {
+            // The main loop only advances time once all pending events have executed.
+            if let Some(missing) = expected.front() {
+                return Err(format!(
+                    "event {} is {} but {} should have happened first",
+                    index, event, missing
+                ));
+            }
+            let scheduled = services.ready_time(store);
+            if *time != scheduled {
+                return Err(format!(
+                    "event {} is {} but time should have advanced to {}",
+                    index, event, scheduled
+                ));
+            }
+            players_turn = store.lookup_time(&PLAYER, Predicate::Ready) == Some(*time);
+        } else if expected.front() == Some(event) {
+            expected.pop_front();
+            before_player = before_player.saturating_sub(1);
+        } else if let Some(front) = expected.front() {
+            // Anything else has to have come from outside the services, i.e. the
+            // player's actions.
+            if !players_turn || before_player > 0 || !is_player_action(event) {
+                return Err(format!(
+                    "event {} is {} but expected {}",
+                    index, event, front
+                ));
+            }
+        }
+
+        executed.append(event);
+        dispatch_event(store, services, &mut terminal, rng, event, &mut expected);
+        if let Event::AdvanceTime(_) = event {
+            before_player = expected.len();
+        }
+    }
+
+    let loc = store.lookup_pt(&PLAYER, Predicate::Loc);
+    let ready = store.lookup_time(&PLAYER, Predicate::Ready);
+    info!(root_logger, "replayed"; "events" => events.len(), "player loc" => ?loc, "player ready" => ?ready);
+    Ok(expected)
+}
+
+/// Returns true for events that the terminal queues up for the player.
+fn is_player_action(event: &Event) -> bool {
+    match event {
+        Event::AddMessage(Topic::Error, _) | Event::SetPlayer(_) | Event::SetPlayerReady(_) => true,
+        Event::Attacked(attacker, _, _, _, _) => *attacker == *PLAYER,
+        _ => false,
+    }
 }
 
 fn process_events(
@@ -99,9 +555,11 @@ fn process_events(
     pending: &mut PendingEvents,
     executed: &mut ExecutedEvents,
     store: &mut Store,
+    services: &mut Services,
     terminal: &mut Terminal,
     rng: &mut SmallRng,
 ) -> TerminalEventResult {
+    let mut result = TerminalEventResult::Running;
     while !pending.is_empty() {
         // Grab the next event,
         let event = pending.pop_front();
@@ -112,22 +570,421 @@ fn process_events(
         executed.append(&event);
 
         // and give each service a chance to respond to the event.
-        on_level_event(store, &event, pending);
-        on_level_gen_event(store, &event, pending);
-        on_player_event(store, rng, &event, pending);
-        match terminal.on_event(&event, pending, store) {
+        match dispatch_event(store, services, terminal, rng, &event, pending) {
             TerminalEventResult::NotRunning => return TerminalEventResult::NotRunning,
+            TerminalEventResult::SaveGame => result = TerminalEventResult::SaveGame,
             TerminalEventResult::Running => (),
         }
     }
-    TerminalEventResult::Running
+    result
 }
 
-fn find_next_scheduled(store: &Store) -> Time {
-    let mut time = INFINITE_TIME;
+fn dispatch_event(
+    store: &mut Store,
+    services: &mut Services,
+    terminal: &mut Terminal,
+    rng: &mut SmallRng,
+    event: &Event,
+    pending: &mut PendingEvents,
+) -> TerminalEventResult {
+    if let Event::NewGame(seed) = event {
+        *rng = SmallRng::seed_from_u64(*seed);
+    }
+
+    // The terminal isn't a Service because it also controls the main loop
+    // (e.g. when the player quits) so it goes last.
+    services.on_event(store, rng, event, pending);
+    if cfg!(debug_assertions) {
+        check_occupancy(store);
+    }
+    terminal.on_event(event, pending, store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use species::test_species;
+
+    fn parse_options(args: &[&str]) -> Result<Options, String> {
+        let args = std::iter::once("crippled_god").chain(args.iter().cloned());
+        let options = Options::from_iter_safe(args).map_err(|err| err.message)?;
+        validate_options(&options)?;
+        Ok(options)
+    }
+
+    fn character(species: &str, background: &str) -> Character {
+        Character {
+            name: "Kalam".to_string(),
+            species: species.to_string(),
+            background: background.to_string(),
+        }
+    }
+
+    // Plays a game without any input (other than making the player wait).
+    fn play(logger: &slog::Logger, seed: u64) -> (Store, ExecutedEvents, SmallRng) {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut services = new_services(&test_species(), test_spawns());
+        let mut terminal = Terminal::new_headless(logger);
+        let (mut store, mut executed, mut pending) =
+            new_game(logger, seed, character("human", "Marine"));
+        process_events(
+            logger,
+            &mut pending,
+            &mut executed,
+            &mut store,
+            &mut services,
+            &mut terminal,
+            &mut rng,
+        );
+
+        // The player's action is queued up after time advances (like Terminal does).
+        let time = services.ready_time(&store);
+        for event in [
+            Event::AdvanceTime(time),
+            Event::SetPlayerReady(time + Duration::from_secs(2.0)),
+        ]
+        .iter()
+        {
+            pending.push_back(event.clone());
+            process_events(
+                logger,
+                &mut pending,
+                &mut executed,
+                &mut store,
+                &mut services,
+                &mut terminal,
+                &mut rng,
+            );
+        }
+        (store, executed, rng)
+    }
+
+    fn events_with(
+        executed: &ExecutedEvents,
+        replace: impl Fn(&Event) -> Option<Event>,
+    ) -> ExecutedEvents {
+        let mut events = ExecutedEvents::new();
+        for event in executed.iter() {
+            events.append(&replace(event).unwrap_or_else(|| event.clone()));
+        }
+        events
+    }
+
+    #[test]
+    fn test_options() {
+        let options = parse_options(&[]).unwrap();
+        assert_eq!(options.seed, None);
+        assert_eq!(options.log_level, sloggers::types::Severity::Debug);
+        assert_eq!(options.log_path, PathBuf::from("crippled-god.log"));
+        assert_eq!(options.replay, None);
+        assert_eq!(options.event_format, EventFormat::Text);
+        assert!(options.convert.is_empty());
+        assert_eq!(options.script, None);
+
+        let options = parse_options(&["--seed", "7", "--log-level", "trace"]).unwrap();
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.log_level, sloggers::types::Severity::Trace);
+
+        let options = parse_options(&["--event-format", "binary"]).unwrap();
+        assert_eq!(options.event_format, EventFormat::Binary);
+
+        let options = parse_options(&["--convert", "Cargo.toml", "out.bin"]).unwrap();
+        assert_eq!(
+            options.convert,
+            vec![PathBuf::from("Cargo.toml"), PathBuf::from("out.bin")]
+        );
+    }
+
+    #[test]
+    fn test_bad_options() {
+        assert!(parse_options(&["--seed", "-1"]).is_err());
+        assert!(parse_options(&["--seed", "seven"]).is_err());
+        assert!(parse_options(&["--log-level", "chatty"]).is_err());
+        assert!(parse_options(&["--log-path", "x", "--save-path", "x"]).is_err());
+        assert!(parse_options(&["--journal-path", "crippled-god.log"]).is_err());
+        assert!(parse_options(&["--replay", "/no/such/file"]).is_err());
+        assert!(parse_options(&["--bogus"]).is_err());
+        assert!(parse_options(&["--event-format", "json"]).is_err());
+        assert!(parse_options(&["--convert", "Cargo.toml"]).is_err());
+        assert!(parse_options(&["--convert", "/no/such/file", "out.bin"]).is_err());
+        assert!(parse_options(&["--convert", "Cargo.toml", "Cargo.toml"]).is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (store, executed, mut rng) = play(&logger, 1);
 
-    time = std::cmp::min(time, level_gen_ready_time(store));
-    time = std::cmp::min(time, player_ready_time(store));
+        // Replay it starting with a different seed.
+        let mut rng2 = SmallRng::seed_from_u64(100);
+        let mut replayed = Store::new(&logger);
+        let mut executed2 = ExecutedEvents::new();
+        let pending = replay(
+            &logger,
+            &executed,
+            &mut executed2,
+            &mut replayed,
+            &mut new_services(&test_species(), test_spawns()),
+            &mut rng2,
+        )
+        .unwrap();
+        assert!(pending.is_empty());
 
-    time
+        assert_eq!(executed.len(), executed2.len());
+        assert_eq!(player_loc(&replayed), player_loc(&store));
+        assert_eq!(player_ready_time(&replayed), player_ready_time(&store));
+        assert_eq!(get_level_size(&replayed), get_level_size(&store));
+        let size = get_level_size(&store);
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let loc = Point::new(x, y);
+                assert_eq!(
+                    get_level_terrain(&replayed, loc),
+                    get_level_terrain(&store, loc)
+                );
+            }
+        }
+
+        // The game should continue just as the original would have.
+        assert_eq!(rng.next_u64(), rng2.next_u64());
+    }
+
+    #[test]
+    fn test_replay_truncated() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (store, executed, _) = play(&logger, 2);
+
+        let mut truncated = ExecutedEvents::new();
+        for event in executed.iter().take(100) {
+            truncated.append(event);
+        }
+
+        // Events which were queued up before the "crash" are returned,
+        let mut rng = SmallRng::seed_from_u64(2);
+        let mut replayed = Store::new(&logger);
+        let mut executed2 = ExecutedEvents::new();
+        let mut services = new_services(&test_species(), test_spawns());
+        let mut pending = replay(
+            &logger,
+            &truncated,
+            &mut executed2,
+            &mut replayed,
+            &mut services,
+            &mut rng,
+        )
+        .unwrap();
+        assert!(!pending.is_empty());
+
+        // and executing them gets us back to where we were.
+        let mut terminal = Terminal::new_headless(&logger);
+        process_events(
+            &logger,
+            &mut pending,
+            &mut executed2,
+            &mut replayed,
+            &mut services,
+            &mut terminal,
+            &mut rng,
+        );
+        assert_eq!(player_loc(&replayed), player_loc(&store));
+    }
+
+    #[test]
+    fn test_unknown_saved_data() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (_, executed, _) = play(&logger, 3);
+        let resume = |events| {
+            resume_game(
+                &logger,
+                Ok(events),
+                &test_species(),
+                &mut new_services(&test_species(), test_spawns()),
+                &mut SmallRng::seed_from_u64(3),
+            )
+            .err()
+            .expect("resume should have failed")
+        };
+
+        let events = events_with(&executed, |event| match event {
+            Event::NewCharacter(name, _, background) => Some(Event::NewCharacter(
+                name.clone(),
+                "jaghut".to_string(),
+                background.clone(),
+            )),
+            _ => None,
+        });
+        assert_eq!(resume(events), "event 1 uses unknown species jaghut");
+
+        let events = events_with(&executed, |event| match event {
+            Event::NewCharacter(name, species, _) => Some(Event::NewCharacter(
+                name.clone(),
+                species.clone(),
+                "Priest".to_string(),
+            )),
+            _ => None,
+        });
+        assert_eq!(resume(events), "event 1 uses unknown background Priest");
+
+        let events = events_with(&executed, |event| match event {
+            Event::NewNPC(loc, _, hps, ready) => {
+                Some(Event::NewNPC(*loc, "jaghut".to_string(), *hps, *ready))
+            }
+            _ => None,
+        });
+        let err = resume(events);
+        assert!(err.ends_with("uses unknown species jaghut"), "{}", err);
+    }
+
+    #[test]
+    fn test_replay_diverged() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (_, executed, _) = play(&logger, 3);
+
+        // Pretend that the player was placed somewhere else.
+        let events = events_with(&executed, |event| match event {
+            Event::SetPlayer(loc) => Some(Event::SetPlayer(Point::new(loc.x + 1, loc.y))),
+            _ => None,
+        });
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut executed2 = ExecutedEvents::new();
+        let err = replay(
+            &logger,
+            &events,
+            &mut executed2,
+            &mut Store::new(&logger),
+            &mut new_services(&test_species(), test_spawns()),
+            &mut rng,
+        )
+        .err()
+        .expect("replay should have diverged");
+        assert!(err.contains("SetPlayer"), "{}", err);
+
+        // Pretend that time advanced too far.
+        let events = events_with(&executed, |event| match event {
+            Event::AdvanceTime(time) => Some(Event::AdvanceTime(*time + Duration::from_secs(1.0))),
+            _ => None,
+        });
+        let mut executed2 = ExecutedEvents::new();
+        let err = replay(
+            &logger,
+            &events,
+            &mut executed2,
+            &mut Store::new(&logger),
+            &mut new_services(&test_species(), test_spawns()),
+            &mut rng,
+        )
+        .err()
+        .expect("replay should have diverged");
+        assert!(err.contains("AdvanceTime"), "{}", err);
+    }
+
+    #[test]
+    fn test_populate() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (store, executed, _) = play(&logger, 4);
+
+        let npcs: Vec<&Subject> = store.iter_by_instance_class("npc").collect();
+        assert_eq!(npcs.len(), 10);
+
+        // Every character should be in its own cell and the cells should refer
+        // back to the characters.
+        let mut locs = std::collections::HashSet::new();
+        for name in npcs.iter().cloned().chain(std::iter::once(&*PLAYER)) {
+            let loc = store.lookup_pt(name, Predicate::Loc).unwrap();
+            assert!(locs.insert(loc), "{} shares {}", name, loc);
+            assert_eq!(
+                store.lookup_ref(&cell(loc), Predicate::Character).as_ref(),
+                Some(name)
+            );
+            assert_eq!(get_level_terrain(&store, loc), Terrain::Ground);
+        }
+
+        let species: Vec<String> = npcs
+            .iter()
+            .map(|name| store.lookup_str(name, Predicate::Species).unwrap())
+            .collect();
+        assert_eq!(species.iter().filter(|s| *s == "ay").count(), 5);
+        assert!(npcs
+            .iter()
+            .all(|name| store.lookup_int(name, Predicate::HPs).unwrap() > 0));
+
+        // The NPCs are added after the player is placed.
+        let names: Vec<&str> = executed.iter().map(|event| event.name()).collect();
+        let set_player = names.iter().position(|n| *n == "SetPlayer").unwrap();
+        let populate = names.iter().position(|n| *n == "PopulateLevel").unwrap();
+        let first_npc = names.iter().position(|n| *n == "NewNPC").unwrap();
+        assert!(set_player < populate && populate < first_npc);
+    }
+
+    #[test]
+    fn test_custom_spawns() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let species = test_species();
+        let mut services = Services::new();
+        services.register(Box::new(LevelService));
+        services.register(Box::new(LevelGenService::new(
+            species.clone(),
+            vec![Spawn {
+                species: "bhederin".to_string(),
+                count: 3,
+            }],
+        )));
+        services.register(Box::new(PlayerService::new(species.clone())));
+        services.register(Box::new(NPCService::new(species)));
+
+        let mut store = Store::new(&logger);
+        let mut pending = PendingEvents::new();
+        pending.push_back(Event::NewGame(5));
+        pending.push_back(Event::NewBranch);
+        process_events(
+            &logger,
+            &mut pending,
+            &mut ExecutedEvents::new(),
+            &mut store,
+            &mut services,
+            &mut Terminal::new_headless(&logger),
+            &mut SmallRng::seed_from_u64(5),
+        );
+
+        let npcs: Vec<&Subject> = store.iter_by_instance_class("npc").collect();
+        assert_eq!(npcs.len(), 3);
+        for name in npcs {
+            assert_eq!(
+                store.lookup_str(name, Predicate::Species).unwrap(),
+                "bhederin"
+            );
+            assert_eq!(store.lookup_int(name, Predicate::HPs), Some(24));
+            assert_eq!(
+                store.lookup_time(name, Predicate::Ready),
+                Some(Time::from_secs(1.1))
+            );
+        }
+    }
+
+    #[test]
+    fn test_new_character() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let (mut store, mut executed, mut pending) =
+            new_game(&logger, 6, character("tiste-andii", "Claw"));
+        process_events(
+            &logger,
+            &mut pending,
+            &mut executed,
+            &mut store,
+            &mut new_services(&test_species(), test_spawns()),
+            &mut Terminal::new_headless(&logger),
+            &mut SmallRng::seed_from_u64(6),
+        );
+
+        let lookup_str = |predicate| store.lookup_str(&PLAYER, predicate).unwrap();
+        assert_eq!(lookup_str(Predicate::Name), "Kalam");
+        assert_eq!(lookup_str(Predicate::Species), "tiste-andii");
+        assert_eq!(lookup_str(Predicate::Background), "Claw");
+
+        // Tiste Andii start with 65 hit points and Claws lose 10 of those.
+        assert_eq!(store.lookup_int(&PLAYER, Predicate::HPs), Some(55));
+        assert_eq!(store.lookup_int(&PLAYER, Predicate::MaxHPs), Some(55));
+        assert_eq!(store.lookup_int(&PLAYER, Predicate::DamageBonus), Some(6));
+    }
 }
@@ -0,0 +1,159 @@
+//! Messages are text shown to the user, e.g. to describe each blow in a fight.
+//! They are added with AddMessage events and stored along with their topic and
+//! the game time so that they can be shown in a scrollback viewer.
+use super::core::*;
+use super::level::*;
+use super::service::*;
+use rand::rngs::SmallRng;
+
+/// Number of messages retained for the scrollback viewer.
+const SCROLL_BACK: i32 = 100;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+	pub topic: Topic,
+	pub text: String,
+	pub time: Time,
+}
+
+/// Returns the retained messages, oldest first.
+pub fn messages(store: &Store) -> Vec<Message> {
+	recent_messages(store, SCROLL_BACK)
+}
+
+/// Returns up to count of the most recent messages, oldest first.
+pub fn recent_messages(store: &Store, count: i32) -> Vec<Message> {
+	let num = num_messages(store);
+	let start = (num - count).max(0);
+	(start..num)
+		.filter_map(|index| {
+			let subject = message(index);
+			match (
+				store.lookup_topic(&subject, Predicate::Topic),
+				store.lookup_str(&subject, Predicate::Text),
+				store.lookup_time(&subject, Predicate::Time),
+			) {
+				(Some(topic), Some(text), Some(time)) => Some(Message { topic, text, time }),
+				_ => None,
+			}
+		})
+		.collect()
+}
+
+/// Maintains the message log.
+pub struct MessageService;
+
+impl Service for MessageService {
+	fn on_event(
+		&mut self,
+		store: &mut Store,
+		_rng: &mut SmallRng,
+		event: &Event,
+		_pending: &mut PendingEvents,
+	) {
+		if let Event::AddMessage(topic, text) = event {
+			add_message(store, event, *topic, text);
+		}
+	}
+}
+
+// --- Private Items ----------------------------------------------------------
+fn add_message(store: &mut Store, event: &Event, topic: Topic, text: &str) {
+	let index = num_messages(store);
+	let subject = message(index);
+	let time = current_time(store);
+	store.insert(event, &subject, Predicate::Topic, Object::Topic(topic));
+	store.insert(
+		event,
+		&subject,
+		Predicate::Text,
+		Object::Str(text.to_string()),
+	);
+	store.insert(event, &subject, Predicate::Time, Object::Time(time));
+	store.insert(
+		event,
+		&LEVEL,
+		Predicate::NumMessages,
+		Object::Int(index + 1),
+	);
+
+	if index >= SCROLL_BACK {
+		let old = message(index - SCROLL_BACK);
+		store.remove(event, &old, Predicate::Topic);
+		store.remove(event, &old, Predicate::Text);
+		store.remove(event, &old, Predicate::Time);
+	}
+}
+
+fn num_messages(store: &Store) -> i32 {
+	store
+		.lookup_int(&LEVEL, Predicate::NumMessages)
+		.unwrap_or(0)
+}
+
+fn message(index: i32) -> Subject {
+	Subject::new_unique(&format!("message-{}", index))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::SeedableRng;
+
+	fn add(store: &mut Store, text: &str) {
+		let mut rng = SmallRng::seed_from_u64(1);
+		let mut pending = PendingEvents::new();
+		let event = Event::AddMessage(Topic::Warning, text.to_string());
+		MessageService.on_event(store, &mut rng, &event, &mut pending);
+		assert!(pending.is_empty());
+	}
+
+	#[test]
+	fn test_messages() {
+		let logger = slog::Logger::root(slog::Discard, o!());
+		let mut store = Store::new(&logger);
+		assert!(messages(&store).is_empty());
+
+		add(&mut store, "first");
+		let event = Event::AdvanceTime(Time(30));
+		store.insert(&event, &LEVEL, Predicate::Time, Object::Time(Time(30)));
+		add(&mut store, "second");
+
+		assert_eq!(
+			messages(&store),
+			vec![
+				Message {
+					topic: Topic::Warning,
+					text: "first".to_string(),
+					time: Time(0)
+				},
+				Message {
+					topic: Topic::Warning,
+					text: "second".to_string(),
+					time: Time(30)
+				},
+			]
+		);
+		let recent = recent_messages(&store, 1);
+		assert_eq!(recent.len(), 1);
+		assert_eq!(recent[0].text, "second");
+	}
+
+	#[test]
+	fn test_scroll_back() {
+		let logger = slog::Logger::root(slog::Discard, o!());
+		let mut store = Store::new(&logger);
+		for i in 0..SCROLL_BACK + 5 {
+			add(&mut store, &format!("message {}", i));
+		}
+
+		let all = messages(&store);
+		assert_eq!(all.len(), SCROLL_BACK as usize);
+		assert_eq!(all[0].text, "message 5");
+		assert_eq!(
+			store.lookup_str(&message(4), Predicate::Text),
+			None,
+			"old messages should be removed"
+		);
+	}
+}
@@ -1,24 +1,56 @@
 use super::character::*;
+use super::combat::*;
 use super::core::*;
 use super::level::*;
+use super::service::*;
+use super::species::*;
+use fnv::FnvHashSet;
 use rand::rngs::SmallRng;
-// use rand::seq::SliceRandom;
-
-// TODO: need to do this via an event
-// pub fn new_npc(store: &mut Store, prefix: &str, rng: &mut SmallRng, current_time: Time) {
-// 	if let Some(loc) = find_char_loc(store, rng) {
-// 		let name = Subject::new_instance(store, "npc", prefix);
-// 		store.insert(&name, Predicate::Loc, Object::Point(loc));
-// 		store.insert(
-// 			&name,
-// 			Predicate::Ready,
-// 			Object::Time(current_time + Duration::from_secs(1.1)),
-// 		);
-// 	}
-// 	// TODO: else log?
-// }
-
-pub fn npc_ready_time(store: &Store) -> Time {
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::rc::Rc;
+
+fn new_npc(store: &mut Store, event: &Event, loc: Point, species: &str, hps: i32, ready: Time) {
+	let name = Subject::new_instance(store, "npc", species);
+	set_char_loc(store, event, &name, loc);
+	store.insert(
+		event,
+		&name,
+		Predicate::Species,
+		Object::Str(species.to_string()),
+	);
+	store.insert(event, &name, Predicate::HPs, Object::Int(hps));
+	store.insert(event, &name, Predicate::MaxHPs, Object::Int(hps));
+	store.insert(event, &name, Predicate::Ready, Object::Time(ready));
+}
+
+pub struct NPCService {
+	species: Rc<SpeciesTable>,
+}
+
+impl NPCService {
+	pub fn new(species: Rc<SpeciesTable>) -> NPCService {
+		NPCService { species }
+	}
+}
+
+impl Service for NPCService {
+	fn on_event(
+		&mut self,
+		store: &mut Store,
+		rng: &mut SmallRng,
+		event: &Event,
+		pending: &mut PendingEvents,
+	) {
+		on_npc_event(store, &self.species, rng, event, pending);
+	}
+
+	fn ready_time(&self, store: &Store) -> Time {
+		npc_ready_time(store)
+	}
+}
+
+fn npc_ready_time(store: &Store) -> Time {
 	let mut time = INFINITE_TIME;
 
 	for name in store.iter_by_instance_class("npc") {
@@ -31,39 +63,416 @@ pub fn npc_ready_time(store: &Store) -> Time {
 	time
 }
 
-pub fn on_npc_event(
+fn on_npc_event(
 	store: &mut Store,
+	species: &SpeciesTable,
 	rng: &mut SmallRng,
 	event: &Event,
 	pending: &mut PendingEvents,
 ) {
 	match event {
-		Event::AdvanceTime(time) => {
-			let names: Vec<Subject> = store
-				.iter_by_instance_class("npc")
-				.filter(|name| {
-					let ready = store.lookup_time(name, Predicate::Ready).unwrap();
-					assert!(*time >= ready);
-					*time == ready
-				})
-				.cloned()
-				.collect();
-			for name in names.iter() {
-				do_skittish(store, rng, pending, name);
-			}
+		Event::AdvanceTime(time) => on_advance_time(store, species, rng, *time, pending),
+		Event::Died(name) if *name != *PLAYER => {
+			remove_char(store, event, name);
+			store.remove(event, name, Predicate::Ready);
+			store.remove_instance(event, "npc", name);
+		}
+		Event::NewNPC(loc, species, hps, ready) => {
+			new_npc(store, event, *loc, species, *hps, *ready)
+		}
+		Event::SetNPC(name, loc) => {
+			// The player may have moved into loc after the NPC decided to move there.
+			let _ = move_char_to(store, event, name, *loc);
+		}
+		Event::SetNPCReady(name, time) => {
+			store.insert(event, name, Predicate::Ready, Object::Time(*time));
 		}
 		_ => (),
 	}
 }
 
-fn do_skittish(store: &mut Store, rng: &mut SmallRng, pending: &mut PendingEvents, name: &Subject) {
+fn on_advance_time(
+	store: &mut Store,
+	table: &SpeciesTable,
+	rng: &mut SmallRng,
+	time: Time,
+	pending: &mut PendingEvents,
+) {
+	let mut names: Vec<Subject> = store
+		.iter_by_instance_class("npc")
+		.filter(|name| {
+			let ready = store.lookup_time(name, Predicate::Ready).unwrap();
+			assert!(ready >= time, "{} should have acted at {}", name, ready);
+			time == ready
+		})
+		.cloned()
+		.collect();
+	names.sort(); // so that the RNG is used in the same order when replaying
+
+	// The SetNPC events won't execute until we return so we need to keep track
+	// of where NPCs are moving to so that two NPCs don't move into the same cell.
+	let mut claimed = FnvHashSet::default();
+	for name in names.iter() {
+		let duration = match table.of(store, name).behavior {
+			Behavior::Aggressive => do_aggressive(store, table, rng, pending, name, &mut claimed),
+			Behavior::Passive => do_wander(store, table, rng, pending, name, &mut claimed),
+			Behavior::Skittish => do_skittish(store, table, rng, pending, name, &mut claimed),
+		};
+		pending.push_back(Event::SetNPCReady(name.clone(), time + duration));
+	}
+}
+
+fn do_aggressive(
+	store: &Store,
+	table: &SpeciesTable,
+	rng: &mut SmallRng,
+	pending: &mut PendingEvents,
+	name: &Subject,
+	claimed: &mut FnvHashSet<Point>,
+) -> Duration {
+	let player_loc = store.lookup_pt(&PLAYER, Predicate::Loc).unwrap();
+	let loc = store.lookup_pt(name, Predicate::Loc).unwrap();
+	if player_loc.distance2(loc) <= 2 && is_alive(store, &PLAYER) {
+		return attack(store, table, pending, name, &PLAYER);
+	}
+
+	let left_is_better = |lhs: Point, rhs: Point| {
+		let d1 = player_loc.distance2(lhs);
+		let d2 = player_loc.distance2(rhs);
+		d1 > 0 && d1 < d2
+	};
+	move_relative_to_player(store, table, rng, pending, name, claimed, left_is_better)
+		.unwrap_or(NO_OP_DURATION)
 }
 
-fn move_npc_by(store: &mut Store, name: &Subject, dx: i32, dy: i32) -> Option<Duration> {
-	if let Some((duration, new_loc)) = move_char_by(store, name, dx, dy) {
-		// pending.push_back(Event::SetNPC(new_loc)); // TODO
-		Some(duration)
+fn do_skittish(
+	store: &Store,
+	table: &SpeciesTable,
+	rng: &mut SmallRng,
+	pending: &mut PendingEvents,
+	name: &Subject,
+	claimed: &mut FnvHashSet<Point>,
+) -> Duration {
+	let player_loc = store.lookup_pt(&PLAYER, Predicate::Loc).unwrap();
+	let left_is_better = |lhs: Point, rhs: Point| {
+		let d1 = player_loc.distance2(lhs);
+		let d2 = player_loc.distance2(rhs);
+		d1 > d2
+	};
+	move_relative_to_player(store, table, rng, pending, name, claimed, left_is_better)
+		.unwrap_or_else(|| do_wander(store, table, rng, pending, name, claimed))
+}
+
+fn do_wander(
+	store: &Store,
+	table: &SpeciesTable,
+	rng: &mut SmallRng,
+	pending: &mut PendingEvents,
+	name: &Subject,
+	claimed: &mut FnvHashSet<Point>,
+) -> Duration {
+	// Wandering characters spend most of their time standing around.
+	if rng.gen_bool(0.75) {
+		return NO_OP_DURATION;
+	}
+
+	let mut deltas = DELTAS.to_vec();
+	deltas.shuffle(rng);
+	for delta in deltas {
+		if let Some(duration) = move_npc_by(store, table, pending, name, delta, claimed) {
+			return duration;
+		}
+	}
+	NO_OP_DURATION
+}
+
+/// Returns None if the player isn't visible, otherwise moves to the best adjacent
+/// cell (or stays put if there isn't one).
+fn move_relative_to_player<F: Fn(Point, Point) -> bool>(
+	store: &Store,
+	table: &SpeciesTable,
+	rng: &mut SmallRng,
+	pending: &mut PendingEvents,
+	name: &Subject,
+	claimed: &mut FnvHashSet<Point>,
+	left_is_better: F,
+) -> Option<Duration> {
+	let loc = store.lookup_pt(name, Predicate::Loc).unwrap();
+	let player_loc = store.lookup_pt(&PLAYER, Predicate::Loc).unwrap();
+	if !is_visible(store, loc, player_loc) {
+		return None;
+	}
+
+	// Shuffle so that ties are broken randomly.
+	let mut deltas = DELTAS.to_vec();
+	deltas.shuffle(rng);
+
+	let species = table.of(store, name);
+	let mut best = (0, 0);
+	for (dx, dy) in deltas {
+		let candidate = Point::new(loc.x + dx, loc.y + dy);
+		let current = Point::new(loc.x + best.0, loc.y + best.1);
+		if can_npc_move_to(store, species, candidate, claimed) && left_is_better(candidate, current)
+		{
+			best = (dx, dy);
+		}
+	}
+
+	if best != (0, 0) {
+		move_npc_by(store, table, pending, name, best, claimed)
 	} else {
-		None
+		Some(NO_OP_DURATION)
+	}
+}
+
+const DELTAS: [(i32, i32); 8] = [
+	(-1, -1),
+	(-1, 0),
+	(-1, 1),
+	(0, -1),
+	(0, 1),
+	(1, -1),
+	(1, 0),
+	(1, 1),
+];
+
+// Other NPCs may have already decided to move into loc this turn.
+fn can_npc_move_to(
+	store: &Store,
+	species: &Species,
+	loc: Point,
+	claimed: &FnvHashSet<Point>,
+) -> bool {
+	can_move_to(store, species, loc) && !claimed.contains(&loc)
+}
+
+fn move_npc_by(
+	store: &Store,
+	table: &SpeciesTable,
+	pending: &mut PendingEvents,
+	name: &Subject,
+	delta: (i32, i32),
+	claimed: &mut FnvHashSet<Point>,
+) -> Option<Duration> {
+	let species = table.of(store, name);
+	if let Some((duration, new_loc)) = move_char_by(store, species, name, delta.0, delta.1) {
+		if can_npc_move_to(store, species, new_loc, claimed) {
+			claimed.insert(new_loc);
+			pending.push_back(Event::SetNPC(name.clone(), new_loc));
+			return Some(duration);
+		}
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::level::LevelService;
+	use super::super::player::PlayerService;
+	use super::*;
+	use rand::SeedableRng;
+
+	struct Game {
+		store: Store,
+		services: Services,
+		rng: SmallRng,
+	}
+
+	impl Game {
+		// Player is at (5, 5) in a 12x12 level.
+		fn new(npcs: &[(&str, Point)], walls: &[Point]) -> Game {
+			let logger = slog::Logger::root(slog::Discard, o!());
+			let mut services = Services::new();
+			services.register(Box::new(LevelService));
+			services.register(Box::new(CombatService));
+			let species = test_species();
+			services.register(Box::new(PlayerService::new(species.clone())));
+			services.register(Box::new(NPCService::new(species)));
+
+			let mut game = Game {
+				store: Store::new(&logger),
+				services,
+				rng: SmallRng::seed_from_u64(1),
+			};
+			game.run(Event::NewGame(1));
+			game.run(Event::ResetLevel(
+				"test".to_string(),
+				Size::new(12, 12),
+				Terrain::Ground,
+			));
+			for loc in walls {
+				game.run(Event::SetTerrain(*loc, Terrain::Wall));
+			}
+			game.run(Event::SetPlayer(Point::new(5, 5)));
+			for (species, loc) in npcs {
+				game.run(Event::NewNPC(*loc, species.to_string(), 10, Time(10)));
+			}
+			game
+		}
+
+		// Executes the event along with any events it queued up.
+		fn run(&mut self, event: Event) -> Vec<Event> {
+			let mut pending = PendingEvents::new();
+			pending.push_back(event);
+
+			let mut executed = Vec::new();
+			while !pending.is_empty() {
+				let event = pending.pop_front();
+				self.services
+					.on_event(&mut self.store, &mut self.rng, &event, &mut pending);
+				check_occupancy(&self.store);
+				executed.push(event);
+			}
+			executed
+		}
+
+		fn npc_locs(&self) -> Vec<Point> {
+			let mut names: Vec<&Subject> = self.store.iter_by_instance_class("npc").collect();
+			names.sort();
+			names
+				.iter()
+				.map(|name| self.store.lookup_pt(name, Predicate::Loc).unwrap())
+				.collect()
+		}
+	}
+
+	fn moves(events: &[Event]) -> Vec<Point> {
+		events
+			.iter()
+			.filter_map(|event| match event {
+				Event::SetNPC(_, loc) => Some(*loc),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_skittish_flees() {
+		let player_loc = Point::new(5, 5);
+		let mut game = Game::new(&[("bhederin", Point::new(7, 5))], &[]);
+		let events = game.run(Event::AdvanceTime(Time(10)));
+
+		let moves = moves(&events);
+		assert_eq!(moves.len(), 1);
+		assert!(player_loc.distance2(moves[0]) > 4, "moved to {}", moves[0]);
+		assert_eq!(game.npc_locs(), moves);
+
+		// NPCs are rescheduled after they act (bhederin have a 0.9 move_scale and
+		// diagonal moves take longer).
+		let duration = if moves[0].y == 5 { 18 } else { 25 };
+		assert_eq!(npc_ready_time(&game.store), Time(10 + duration));
+	}
+
+	#[test]
+	fn test_aggressive_approaches() {
+		let player_loc = Point::new(5, 5);
+		let mut game = Game::new(&[("ay", Point::new(8, 5))], &[]);
+		for i in 0..4 {
+			let time = npc_ready_time(&game.store);
+			let events = game.run(Event::AdvanceTime(time));
+			if i < 2 {
+				assert_eq!(moves(&events).len(), 1);
+			}
+		}
+
+		// The ay should now be adjacent to the player (but not on top of the player).
+		let loc = game.npc_locs()[0];
+		assert!(player_loc.distance2(loc) <= 2, "ay is at {}", loc);
+		assert!(loc != player_loc);
+	}
+
+	#[test]
+	fn test_aggressive_attacks() {
+		let mut game = Game::new(&[("ay", Point::new(6, 6))], &[]);
+		let events = game.run(Event::AdvanceTime(Time(10)));
+		assert!(moves(&events).is_empty());
+		assert!(events.iter().any(
+			|event| matches!(event, Event::Attacked(_, defender, _, _, 10) if *defender == *PLAYER)
+		));
+		assert_eq!(char_hps(&game.store, &PLAYER), 75 - 10);
+		assert_eq!(npc_ready_time(&game.store), Time(10) + ATTACK_DURATION);
+	}
+
+	#[test]
+	fn test_aggressive_needs_los() {
+		// Wall off the player.
+		let mut walls = Vec::new();
+		for y in 0..12 {
+			walls.push(Point::new(7, y));
+		}
+		let mut game = Game::new(&[("ay", Point::new(9, 5))], &walls);
+		let events = game.run(Event::AdvanceTime(Time(10)));
+		assert!(moves(&events).is_empty());
+		assert_eq!(game.npc_locs(), vec![Point::new(9, 5)]);
+	}
+
+	#[test]
+	fn test_back_refs() {
+		let mut game = Game::new(&[("bhederin", Point::new(6, 5))], &[]);
+		let name = game
+			.store
+			.lookup_ref(&cell(Point::new(6, 5)), Predicate::Character);
+		assert!(name.is_some());
+
+		game.run(Event::AdvanceTime(Time(10)));
+		let loc = game.npc_locs()[0];
+		assert!(loc != Point::new(6, 5));
+		assert_eq!(
+			game.store
+				.lookup_ref(&cell(Point::new(6, 5)), Predicate::Character),
+			None
+		);
+		assert_eq!(
+			game.store.lookup_ref(&cell(loc), Predicate::Character),
+			name
+		);
+	}
+
+	#[test]
+	fn test_no_collisions() {
+		// Lots of aggressive NPCs that all want to get next to the player.
+		let mut npcs = Vec::new();
+		for x in 1..11 {
+			npcs.push(("ay", Point::new(x, 1)));
+			npcs.push(("ay", Point::new(x, 9)));
+		}
+		let mut game = Game::new(&npcs, &[]);
+		for _ in 0..10 {
+			let time = npc_ready_time(&game.store);
+			game.run(Event::AdvanceTime(time));
+
+			let mut locs = game.npc_locs();
+			locs.push(Point::new(5, 5));
+			let count = locs.len();
+			locs.sort_by_key(|loc| (loc.x, loc.y));
+			locs.dedup();
+			assert_eq!(locs.len(), count);
+		}
+	}
+
+	#[test]
+	fn test_same_tick_move() {
+		// The ay and the player are both ready at Time(10) and both want (6, 5).
+		// The ay acts first so the player's move has to be dropped.
+		let mut game = Game::new(&[("ay", Point::new(7, 5))], &[]);
+		game.store.insert(
+			&Event::SetPlayerReady(Time(10)),
+			&PLAYER,
+			Predicate::Ready,
+			Object::Time(Time(10)),
+		);
+		let events = game.run(Event::AdvanceTime(Time(10)));
+		let moves = moves(&events);
+		assert_eq!(moves.len(), 1);
+
+		let events = game.run(Event::SetPlayer(moves[0]));
+		assert!(events
+			.iter()
+			.any(|event| matches!(event, Event::AddMessage(Topic::Error, _))));
+		assert_eq!(
+			game.store.lookup_pt(&PLAYER, Predicate::Loc),
+			Some(Point::new(5, 5))
+		);
+		assert_eq!(game.npc_locs(), moves);
 	}
 }
@@ -0,0 +1,141 @@
+//! Games are saved as the list of events that were executed. To resume a game
+//! the events are replayed into a fresh Store. Events may be saved as text or
+//! binary: the format is detected when loading.
+use super::core::*;
+use file_scanner::Scanner;
+use std::fs::File;
+use std::io::{BufWriter, Read, Result, Write};
+use std::path::Path;
+
+pub fn has_saved_game(path: &Path) -> bool {
+    path.is_file()
+}
+
+pub fn load_game(path: &Path) -> Result<ExecutedEvents> {
+    let bytes = std::fs::read(path)?;
+    match EventFormat::detect(&bytes) {
+        EventFormat::Text => ExecutedEvents::load(&mut Scanner::new(&bytes[..])),
+        EventFormat::Binary => ExecutedEvents::load_binary(&bytes),
+    }
+}
+
+/// Used to load the journal written by a game that crashed.
+pub fn recover_game(path: &Path) -> Result<ExecutedEvents> {
+    let bytes = std::fs::read(path)?;
+    match EventFormat::detect(&bytes) {
+        EventFormat::Text => ExecutedEvents::recover(&mut Scanner::new(&bytes[..])),
+        EventFormat::Binary => ExecutedEvents::recover_binary(&bytes),
+    }
+}
+
+pub fn save_game(path: &Path, executed: &ExecutedEvents, format: EventFormat) -> Result<()> {
+    // Write to a temporary file first so that we don't lose the old save if
+    // something goes wrong.
+    let temp = path.with_extension("tmp");
+    {
+        let file = File::create(&temp)?;
+        let mut writer = BufWriter::new(file);
+        match format {
+            EventFormat::Text => executed.save(&mut writer)?,
+            EventFormat::Binary => executed.save_binary(&mut writer)?,
+        }
+        writer.flush()?;
+    }
+    std::fs::rename(&temp, path)
+}
+
+/// Returns the format the game was saved with.
+pub fn saved_format(path: &Path) -> Result<EventFormat> {
+    let mut magic = Vec::new();
+    File::open(path)?
+        .take(binary::MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(EventFormat::detect(&magic))
+}
+
+/// Converts a saved game from text to binary or binary to text. Returns the
+/// format that was written.
+pub fn convert_game(input: &Path, output: &Path) -> Result<EventFormat> {
+    let bytes = std::fs::read(input)?;
+    let (events, format) = match EventFormat::detect(&bytes) {
+        EventFormat::Text => (
+            ExecutedEvents::load(&mut Scanner::new(&bytes[..]))?,
+            EventFormat::Binary,
+        ),
+        EventFormat::Binary => (ExecutedEvents::load_binary(&bytes)?, EventFormat::Text),
+    };
+    save_game(output, &events, format)?;
+    Ok(format)
+}
+
+/// Called when the player dies: no save scumming allowed.
+pub fn delete_saved_game(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = PathBuf::from("/tmp/crippled-god-save.txt");
+        let mut executed = ExecutedEvents::new();
+        executed.append(&Event::NewGame(5));
+        executed.append(&Event::SetPlayer(Point::new(3, 4)));
+        save_game(&path, &executed, EventFormat::Text).unwrap();
+        assert!(has_saved_game(&path));
+        assert!(!has_saved_game(&path.with_extension("tmp")));
+
+        let loaded = load_game(&path).unwrap();
+        let loaded: Vec<Event> = loaded.iter().cloned().collect();
+        assert_eq!(
+            loaded,
+            vec![Event::NewGame(5), Event::SetPlayer(Point::new(3, 4))]
+        );
+
+        delete_saved_game(&path);
+        assert!(!has_saved_game(&path));
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let path = PathBuf::from("/tmp/crippled-god-corrupt.txt");
+        std::fs::write(&path, "NewGame 5\nSetPlayer 3\n").unwrap();
+        let err = load_game(&path).err().expect("load should have failed");
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        let path = PathBuf::from("/tmp/crippled-god-missing.txt");
+        assert!(load_game(&path).is_err());
+    }
+
+    #[test]
+    fn test_convert() {
+        let text = PathBuf::from("/tmp/crippled-god-convert.txt");
+        let bin = PathBuf::from("/tmp/crippled-god-convert.bin");
+        let back = PathBuf::from("/tmp/crippled-god-convert2.txt");
+        let mut executed = ExecutedEvents::new();
+        executed.append(&Event::NewGame(5));
+        executed.append(&Event::ResetLevel(
+            "town".to_string(),
+            Size::new(3, 4),
+            Terrain::Ground,
+        ));
+        save_game(&text, &executed, EventFormat::Text).unwrap();
+
+        assert_eq!(convert_game(&text, &bin).unwrap(), EventFormat::Binary);
+        assert_eq!(convert_game(&bin, &back).unwrap(), EventFormat::Text);
+        assert_eq!(saved_format(&text).unwrap(), EventFormat::Text);
+        assert_eq!(saved_format(&bin).unwrap(), EventFormat::Binary);
+        assert_eq!(
+            std::fs::read_to_string(&text).unwrap(),
+            std::fs::read_to_string(&back).unwrap()
+        );
+
+        let loaded = load_game(&bin).unwrap();
+        let loaded: Vec<Event> = loaded.iter().cloned().collect();
+        let expected: Vec<Event> = executed.iter().cloned().collect();
+        assert_eq!(loaded, expected);
+    }
+}
@@ -1,8 +1,11 @@
 use super::character::*;
+use super::combat::*;
 use super::core::*;
 use super::level::*;
+use super::service::*;
+use super::species::*;
 use rand::rngs::SmallRng;
-use rand::seq::SliceRandom;
+use std::rc::Rc;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PlayerAction {
@@ -14,7 +17,11 @@ pub enum PlayerAction {
 	DeltaSouthEast,
 	DeltaSouthWest,
 	DeltaWest,
+	Help,
+	Look,
 	Quit,
+	SaveGame,
+	ShowMessages,
 }
 
 pub enum PlayerActionResult {
@@ -24,21 +31,113 @@ pub enum PlayerActionResult {
 	Ignored,
 
 	/// Player tried to do something but was unable to, e.g. moving into a wall.
-	Error,
+	/// Includes a message explaining why.
+	Error(String),
 }
 
-pub fn new_player(store: &mut Store) {
-	let event = Event::NewGame;
-	let loc = Point::origin();
-	store.insert(&event, &PLAYER, Predicate::Loc, Object::Point(loc));
+/// Species the player starts out as (NewCharacter changes this).
+pub const PLAYER_SPECIES: &str = "human";
+
+/// Backgrounds adjust the starting stats of the player's species.
+pub struct Background {
+	pub name: &'static str,
+	pub description: &'static str,
+
+	/// Added to the species' max hit points.
+	pub hps: i32,
+
+	/// Added to the damage done by each of the player's attacks.
+	pub damage: i32,
+}
+
+pub const BACKGROUNDS: [Background; 3] = [
+	Background {
+		name: "Marine",
+		description: "Drilled to hold the line, marines can take a beating.",
+		hps: 15,
+		damage: 0,
+	},
+	Background {
+		name: "Sapper",
+		description: "Reckless and tough, sappers hit harder than most.",
+		hps: 5,
+		damage: 3,
+	},
+	Background {
+		name: "Claw",
+		description: "Imperial assassins are fragile but deadly.",
+		hps: -10,
+		damage: 6,
+	},
+];
+
+pub fn find_background(name: &str) -> Option<&'static Background> {
+	BACKGROUNDS
+		.iter()
+		.find(|background| background.name == name)
+}
+
+fn new_player(store: &mut Store, species: &SpeciesTable, event: &Event) {
+	let hps = species.get(PLAYER_SPECIES).unwrap().max_hps;
+	set_char_loc(store, event, &PLAYER, Point::origin());
+	store.insert(
+		event,
+		&PLAYER,
+		Predicate::Species,
+		Object::Str(PLAYER_SPECIES.to_string()),
+	);
+	store.insert(event, &PLAYER, Predicate::HPs, Object::Int(hps));
+	store.insert(event, &PLAYER, Predicate::MaxHPs, Object::Int(hps));
 	store.insert(
-		&event,
+		event,
 		&PLAYER,
 		Predicate::Ready,
 		Object::Time(Time::from_secs(1.0)),
 	);
 }
 
+fn new_character(
+	store: &mut Store,
+	table: &SpeciesTable,
+	event: &Event,
+	name: &str,
+	species: &str,
+	background: &str,
+) {
+	let species = table
+		.get(species)
+		.unwrap_or_else(|| panic!("player has unknown species {}", species));
+	let background =
+		find_background(background).unwrap_or_else(|| panic!("unknown background {}", background));
+	let hps = species.max_hps + background.hps;
+	store.insert(
+		event,
+		&PLAYER,
+		Predicate::Name,
+		Object::Str(name.to_string()),
+	);
+	store.insert(
+		event,
+		&PLAYER,
+		Predicate::Species,
+		Object::Str(species.name.clone()),
+	);
+	store.insert(
+		event,
+		&PLAYER,
+		Predicate::Background,
+		Object::Str(background.name.to_string()),
+	);
+	store.insert(event, &PLAYER, Predicate::HPs, Object::Int(hps));
+	store.insert(event, &PLAYER, Predicate::MaxHPs, Object::Int(hps));
+	store.insert(
+		event,
+		&PLAYER,
+		Predicate::DamageBonus,
+		Object::Int(background.damage),
+	);
+}
+
 pub fn player_loc(store: &Store) -> Point {
 	store.lookup_pt(&PLAYER, Predicate::Loc).unwrap()
 }
@@ -47,20 +146,79 @@ pub fn player_ready_time(store: &Store) -> Time {
 	store.lookup_time(&PLAYER, Predicate::Ready).unwrap()
 }
 
-pub fn on_player_event(
+/// Once the player dies the game is over (and the saved game is deleted).
+pub fn player_is_dead(store: &Store) -> bool {
+	store.lookup_bool(&PLAYER, Predicate::Dead).unwrap_or(false)
+}
+
+/// Returns short descriptions of the conditions currently affecting the player,
+/// e.g. "Wading" when shallow water is slowing the player down.
+pub fn player_effects(store: &Store, species: &SpeciesTable) -> Vec<&'static str> {
+	let mut effects = Vec::new();
+	let species = species.of(store, &PLAYER);
+	let terrain = get_level_terrain(store, player_loc(store));
+	if movement_delay(species, terrain) > movement_delay(species, Terrain::Ground) {
+		effects.push("Wading");
+	}
+	effects
+}
+
+pub struct PlayerService {
+	species: Rc<SpeciesTable>,
+}
+
+impl PlayerService {
+	pub fn new(species: Rc<SpeciesTable>) -> PlayerService {
+		PlayerService { species }
+	}
+}
+
+impl Service for PlayerService {
+	fn on_event(
+		&mut self,
+		store: &mut Store,
+		rng: &mut SmallRng,
+		event: &Event,
+		pending: &mut PendingEvents,
+	) {
+		on_player_event(store, &self.species, rng, event, pending);
+	}
+
+	fn ready_time(&self, store: &Store) -> Time {
+		player_ready_time(store)
+	}
+}
+
+fn on_player_event(
 	store: &mut Store,
+	species: &SpeciesTable,
 	rng: &mut SmallRng,
 	event: &Event,
 	pending: &mut PendingEvents,
 ) {
 	match event {
+		Event::Died(name) if *name == *PLAYER => {
+			store.insert(event, &PLAYER, Predicate::Dead, Object::Bool(true));
+		}
+		Event::NewCharacter(name, race, background) => {
+			new_character(store, species, event, name, race, background)
+		}
+		Event::NewGame(_) => new_player(store, species, event),
 		Event::NewLevel => {
 			let loc = find_char_loc(store, rng).unwrap();
 			pending.push_back(Event::SetPlayer(loc));
 		}
 		Event::SetPlayer(loc) => {
 			// TODO: should have an assert here (or maybe in Level) that loc is sane
-			store.insert(event, &PLAYER, Predicate::Loc, Object::Point(*loc));
+			let moved = move_char_to(store, event, &PLAYER, *loc);
+			if !moved {
+				// An NPC moved into loc after the player decided to move there.
+				let text = "Something got in your way.".to_string();
+				pending.push_back(Event::AddMessage(Topic::Error, text));
+			}
+		}
+		Event::SetPlayerReady(time) => {
+			store.insert(event, &PLAYER, Predicate::Ready, Object::Time(*time));
 		}
 		_ => (),
 	}
@@ -68,32 +226,62 @@ pub fn on_player_event(
 
 pub fn on_player_action(
 	store: &Store,
+	species: &SpeciesTable,
 	pending: &mut PendingEvents,
 	action: PlayerAction,
 ) -> PlayerActionResult {
+	match action_delta(action) {
+		Some((dx, dy)) => move_player_by(store, species, pending, dx, dy),
+		None => PlayerActionResult::Ignored,
+	}
+}
+
+/// Returns the direction associated with the Delta actions.
+pub fn action_delta(action: PlayerAction) -> Option<(i32, i32)> {
 	match action {
-		PlayerAction::DeltaEast => move_player_by(store, pending, 1, 0),
-		PlayerAction::DeltaNorth => move_player_by(store, pending, 0, -1),
-		PlayerAction::DeltaNorthEast => move_player_by(store, pending, 1, -1),
-		PlayerAction::DeltaNorthWest => move_player_by(store, pending, -1, -1),
-		PlayerAction::DeltaSouth => move_player_by(store, pending, 0, 1),
-		PlayerAction::DeltaSouthEast => move_player_by(store, pending, 1, 1),
-		PlayerAction::DeltaSouthWest => move_player_by(store, pending, -1, 1),
-		PlayerAction::DeltaWest => move_player_by(store, pending, -1, 0),
-		_ => PlayerActionResult::Ignored,
+		PlayerAction::DeltaEast => Some((1, 0)),
+		PlayerAction::DeltaNorth => Some((0, -1)),
+		PlayerAction::DeltaNorthEast => Some((1, -1)),
+		PlayerAction::DeltaNorthWest => Some((-1, -1)),
+		PlayerAction::DeltaSouth => Some((0, 1)),
+		PlayerAction::DeltaSouthEast => Some((1, 1)),
+		PlayerAction::DeltaSouthWest => Some((-1, 1)),
+		PlayerAction::DeltaWest => Some((-1, 0)),
+		_ => None,
 	}
 }
 
 fn move_player_by(
 	store: &Store,
+	species: &SpeciesTable,
 	pending: &mut PendingEvents,
 	dx: i32,
 	dy: i32,
 ) -> PlayerActionResult {
-	if let Some((duration, new_loc)) = move_char_by(store, &PLAYER, dx, dy) {
+	// Moving into another character attacks it.
+	let loc = player_loc(store);
+	let target = Point::new(loc.x + dx, loc.y + dy);
+	if let Some(defender) = store.lookup_ref(&cell(target), Predicate::Character) {
+		return PlayerActionResult::Acted(attack(store, species, pending, &PLAYER, &defender));
+	}
+
+	if let Some((duration, new_loc)) =
+		move_char_by(store, species.of(store, &PLAYER), &PLAYER, dx, dy)
+	{
 		pending.push_back(Event::SetPlayer(new_loc));
 		PlayerActionResult::Acted(duration)
 	} else {
-		PlayerActionResult::Error // TODO: should we include a reason?
+		PlayerActionResult::Error(blocked_reason(store, target))
+	}
+}
+
+fn blocked_reason(store: &Store, loc: Point) -> String {
+	if !is_level_loc(store, loc) {
+		return "You can't leave the level.".to_string();
+	}
+	match get_level_terrain(store, loc) {
+		Terrain::DeepWater => "The water is too deep.".to_string(),
+		Terrain::Wall => "There is a wall in the way.".to_string(),
+		_ => "You can't move there.".to_string(),
 	}
 }
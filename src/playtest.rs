@@ -0,0 +1,235 @@
+//! Automated play testing. Games are started with a fixed seed, driven by key
+//! scripts (see ScriptedInput::parse), and rendered into memory so that tests
+//! can check the game state (and the screen) after each step.
+use super::*;
+
+pub struct PlayTest {
+    logger: slog::Logger,
+    store: Store,
+    executed: ExecutedEvents,
+    pending: PendingEvents,
+    services: Services,
+    terminal: Terminal,
+    rng: SmallRng,
+    backend: MemoryBackend,
+}
+
+impl PlayTest {
+    /// Starts a new game with a human Marine named Kalam. The game is run
+    /// until the player is first ready to act.
+    pub fn new(seed: u64) -> PlayTest {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let species = species::test_species();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/keys.txt");
+        let keys = KeyBindings::load(&path).unwrap();
+        let backend = MemoryBackend::new(Size::new(80, 24));
+        let terminal =
+            Terminal::with_backend(&logger, species.clone(), keys, Box::new(backend.clone()));
+        let character = Character {
+            name: "Kalam".to_string(),
+            species: "human".to_string(),
+            background: "Marine".to_string(),
+        };
+        let (store, executed, pending) = new_game(&logger, seed, character);
+        let mut game = PlayTest {
+            services: new_services(&species, test_spawns()),
+            rng: SmallRng::seed_from_u64(seed),
+            logger,
+            store,
+            executed,
+            pending,
+            terminal,
+            backend,
+        };
+        game.play("");
+        game
+    }
+
+    /// Plays the game with the keys from script until they run out (or the
+    /// player quits or dies).
+    pub fn play(&mut self, script: &str) -> &mut PlayTest {
+        self.backend.push_script(script).unwrap();
+        loop {
+            // This is the same as the main loop minus saving.
+            if let TerminalEventResult::NotRunning = process_events(
+                &self.logger,
+                &mut self.pending,
+                &mut self.executed,
+                &mut self.store,
+                &mut self.services,
+                &mut self.terminal,
+                &mut self.rng,
+            ) {
+                break;
+            }
+            let time = self.services.ready_time(&self.store);
+            self.pending.push_back(Event::AdvanceTime(time));
+        }
+        self
+    }
+
+    pub fn player_loc(&self) -> Point {
+        player_loc(&self.store)
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Returns the number of executed events that match.
+    pub fn count_events(&self, matches: impl Fn(&Event) -> bool) -> usize {
+        self.executed.iter().filter(|event| matches(event)).count()
+    }
+
+    /// Returns the text on each line of the screen.
+    pub fn screen(&self) -> Vec<String> {
+        self.backend.lines()
+    }
+
+    /// Replays the executed events into a new store, like resuming a saved
+    /// game does.
+    pub fn replay(&self) -> Result<Store, String> {
+        self.replay_events(&self.executed)
+    }
+
+    /// Saves the game with the text format and replays the events loaded
+    /// back from the file.
+    pub fn save_and_replay(&self, path: &Path) -> Result<Store, String> {
+        save_game(path, &self.executed, EventFormat::Text).map_err(|err| err.to_string())?;
+        let events = load_game(path).map_err(|err| err.to_string())?;
+        if events.len() != self.executed.len() {
+            return Err(format!(
+                "saved {} events but loaded {}",
+                self.executed.len(),
+                events.len()
+            ));
+        }
+        self.replay_events(&events)
+    }
+
+    fn replay_events(&self, events: &ExecutedEvents) -> Result<Store, String> {
+        let mut store = Store::new(&self.logger);
+        let mut services = new_services(&species::test_species(), test_spawns());
+        let mut rng = SmallRng::seed_from_u64(0);
+        replay(
+            &self.logger,
+            events,
+            &mut ExecutedEvents::new(),
+            &mut store,
+            &mut services,
+            &mut rng,
+        )?;
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_player_events(game: &PlayTest) -> usize {
+        game.count_events(|event| matches!(event, Event::SetPlayer(_)))
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut game = PlayTest::new(7);
+        assert_eq!(game.player_loc(), Point::new(56, 21));
+        assert_eq!(
+            set_player_events(&game),
+            1,
+            "player was placed on the level"
+        );
+
+        game.play("6*6");
+        assert_eq!(game.player_loc(), Point::new(62, 21));
+        assert_eq!(set_player_events(&game), 7);
+
+        // The map is centered on the player.
+        let screen = game.screen();
+        assert_eq!(screen[9].chars().nth(40), Some('@'));
+        assert!(screen[18].contains("(62, 21)"), "{}", screen[18]);
+
+        let store = game.replay().unwrap();
+        assert_eq!(player_loc(&store), game.player_loc());
+    }
+
+    #[test]
+    fn test_modal_screens() {
+        // Help, look, and the scrollback don't take any game time.
+        let mut game = PlayTest::new(7);
+        let time = current_time(game.store());
+        game.play("? Space  x 4 Esc  Ctrl-p Esc");
+        assert_eq!(current_time(game.store()), time);
+        assert_eq!(game.player_loc(), Point::new(56, 21));
+        assert!(game.replay().is_ok());
+    }
+
+    #[test]
+    fn test_unbound_key() {
+        let mut game = PlayTest::new(7);
+        let time = current_time(game.store());
+        game.play("Z");
+        assert_eq!(current_time(game.store()), time);
+        let expected = "That key isn't bound to anything. Press ? for help.";
+        assert!(
+            game.screen().iter().any(|line| line.contains(expected)),
+            "{:?}",
+            game.screen()
+        );
+        assert!(game.replay().is_ok());
+    }
+
+    #[test]
+    fn test_quote_key() {
+        // Pressing an unbound " shouldn't make the game unsaveable.
+        let mut game = PlayTest::new(7);
+        game.play("\" 6");
+        assert_eq!(game.player_loc(), Point::new(57, 21));
+        let path = Path::new("/tmp/crippled-god-playtest-quote.txt");
+        let store = game.save_and_replay(path).unwrap();
+        assert_eq!(player_loc(&store), game.player_loc());
+    }
+
+    #[test]
+    fn test_tampered_npc_move() {
+        let mut game = PlayTest::new(7);
+        game.play(&"6*6 4*6 ".repeat(4));
+        let index = game
+            .executed
+            .iter()
+            .position(|event| matches!(event, Event::SetNPC(_, _)))
+            .expect("no NPC moved");
+
+        // The replay should fail at the tampered event even if the events end
+        // there (as they would if the game crashed).
+        for len in &[game.executed.len(), index + 1] {
+            let mut events = ExecutedEvents::new();
+            for (i, event) in game.executed.iter().take(*len).enumerate() {
+                match event {
+                    Event::SetNPC(name, loc) if i == index => {
+                        let loc = Point::new(loc.x + 1, loc.y);
+                        events.append(&Event::SetNPC(name.clone(), loc))
+                    }
+                    _ => events.append(event),
+                }
+            }
+            let err = game
+                .replay_events(&events)
+                .err()
+                .expect("replay should have diverged");
+            assert!(err.starts_with(&format!("event {} ", index)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_quit() {
+        let mut game = PlayTest::new(7);
+        game.play("4 q 4 4");
+        assert_eq!(
+            game.player_loc(),
+            Point::new(55, 21),
+            "keys after quit are ignored"
+        );
+    }
+}
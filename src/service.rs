@@ -0,0 +1,124 @@
+//! Services are the subsystems that make up the game, e.g. the level, the
+//! player, and NPCs. They respond to events by updating the store and queuing
+//! up new events.
+use super::core::*;
+use rand::rngs::SmallRng;
+
+pub trait Service {
+    /// Called for each event as it executes.
+    fn on_event(
+        &mut self,
+        store: &mut Store,
+        rng: &mut SmallRng,
+        event: &Event,
+        pending: &mut PendingEvents,
+    );
+
+    /// Returns the time at which the service next wants to do something, time
+    /// will then be advanced to the min of the services ready times.
+    fn ready_time(&self, _store: &Store) -> Time {
+        INFINITE_TIME
+    }
+}
+
+/// Dispatches events to all of the registered services.
+pub struct Services {
+    services: Vec<Box<dyn Service>>,
+}
+
+impl Services {
+    pub fn new() -> Services {
+        Services {
+            services: Vec::new(),
+        }
+    }
+
+    /// Services are called in the order they were registered.
+    pub fn register(&mut self, service: Box<dyn Service>) {
+        self.services.push(service);
+    }
+
+    pub fn on_event(
+        &mut self,
+        store: &mut Store,
+        rng: &mut SmallRng,
+        event: &Event,
+        pending: &mut PendingEvents,
+    ) {
+        for service in self.services.iter_mut() {
+            service.on_event(store, rng, event, pending);
+        }
+    }
+
+    /// Returns the earliest time at which a service is ready.
+    pub fn ready_time(&self, store: &Store) -> Time {
+        self.services
+            .iter()
+            .map(|service| service.ready_time(store))
+            .min()
+            .unwrap_or(INFINITE_TIME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct TestService {
+        name: &'static str,
+        ready: Time,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Service for TestService {
+        fn on_event(
+            &mut self,
+            _store: &mut Store,
+            _rng: &mut SmallRng,
+            event: &Event,
+            pending: &mut PendingEvents,
+        ) {
+            self.log
+                .borrow_mut()
+                .push(format!("{} {}", self.name, event));
+            if let Event::NewLevel = event {
+                pending.push_back(Event::SetPlayer(Point::new(1, 1)));
+            }
+        }
+
+        fn ready_time(&self, _store: &Store) -> Time {
+            self.ready
+        }
+    }
+
+    #[test]
+    fn test_services() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let mut store = Store::new(&logger);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut pending = PendingEvents::new();
+
+        let mut services = Services::new();
+        assert_eq!(services.ready_time(&store), INFINITE_TIME);
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for (name, ready) in [("b", Time(20)), ("a", Time(10)), ("c", INFINITE_TIME)].iter() {
+            services.register(Box::new(TestService {
+                name,
+                ready: *ready,
+                log: log.clone(),
+            }));
+        }
+        assert_eq!(services.ready_time(&store), Time(10));
+
+        services.on_event(&mut store, &mut rng, &Event::NewLevel, &mut pending);
+        assert_eq!(
+            *log.borrow(),
+            vec!["b NewLevel", "a NewLevel", "c NewLevel"]
+        );
+        assert_eq!(pending.len(), 3);
+    }
+}
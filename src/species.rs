@@ -0,0 +1,426 @@
+//! Species are defined in a data file (see data/species.txt) so that new
+//! creatures can be added without recompiling.
+use super::core::*;
+use super::terminal::color::{self, Color};
+use fnv::FnvHashMap;
+use std::path::Path;
+
+#[derive(Debug, PartialEq)]
+pub struct Attack {
+	/// Used in messages, e.g. "bite".
+	pub verb: String,
+	pub damage: i32,
+	pub brand: Brand,
+}
+
+/// How an NPC reacts to the player.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Behavior {
+	/// Moves towards the player when the player is visible and attacks the
+	/// player when adjacent.
+	Aggressive,
+
+	/// Ignores the player and wanders around.
+	Passive,
+
+	/// Flees from the player when the player is visible, otherwise wanders.
+	Skittish,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flag {
+	/// Can fly over deep water.
+	Airborne,
+
+	/// Can swim through deep water.
+	Aquatic,
+
+	/// Can be chosen as the player's race.
+	Playable,
+}
+
+#[derive(Debug)]
+pub struct Species {
+	pub name: String,
+	pub symbol: char,
+	pub color: Color,
+	pub max_hps: i32,
+
+	/// Multiplies the time it takes to move, smaller is faster.
+	pub move_scale: f32,
+
+	/// Any number of attacks are allowed.
+	pub attacks: Vec<Attack>,
+	pub resistances: Vec<(Brand, i32)>,
+	pub flags: Vec<Flag>,
+	pub behavior: Behavior,
+
+	/// Shown when the player looks at the character, e.g. "A giant wolf that
+	/// hunts in packs.".
+	pub description: Option<String>,
+}
+
+impl Species {
+	/// Range is [0, 100]. At 0 attacks have no damage reduction. At 100 attacks are
+	/// completely blocked.
+	pub fn resistance(&self, brand: Brand) -> i32 {
+		self.resistances
+			.iter()
+			.find(|(b, _)| *b == brand)
+			.map_or(0, |(_, percent)| *percent)
+	}
+
+	pub fn has_flag(&self, flag: Flag) -> bool {
+		self.flags.contains(&flag)
+	}
+}
+
+pub struct SpeciesTable {
+	species: FnvHashMap<String, Species>,
+}
+
+impl SpeciesTable {
+	pub fn load(path: &Path) -> Result<SpeciesTable, String> {
+		let text = std::fs::read_to_string(path)
+			.map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+		SpeciesTable::parse(&text).map_err(|err| format!("{}:{}", path.display(), err))
+	}
+
+	/// Errors are prefixed with the line number, e.g. "12: expected an integer
+	/// but found 'ten'".
+	pub fn parse(text: &str) -> Result<SpeciesTable, String> {
+		let mut table = SpeciesTable {
+			species: FnvHashMap::default(),
+		};
+		let mut current: Option<Builder> = None;
+		for (index, line) in text.lines().enumerate() {
+			let line_num = index + 1;
+			let line = line.split('#').next().unwrap();
+			let mut tokens = line.split_whitespace();
+			let key = match tokens.next() {
+				Some(key) => key,
+				None => continue,
+			};
+			let args: Vec<&str> = tokens.collect();
+			if key == "species" {
+				if let Some(builder) = current.take() {
+					table.add(builder)?;
+				}
+				match args[..] {
+					[name] if table.species.contains_key(name) => {
+						return Err(format!("{}: species {} is already defined", line_num, name))
+					}
+					[name] => current = Some(Builder::new(name, line_num)),
+					_ => return Err(format!("{}: expected 'species NAME'", line_num)),
+				}
+			} else if let Some(builder) = current.as_mut() {
+				builder
+					.set(key, &args)
+					.map_err(|err| format!("{}: {}", line_num, err))?;
+			} else {
+				return Err(format!("{}: expected a species line", line_num));
+			}
+		}
+		if let Some(builder) = current.take() {
+			table.add(builder)?;
+		}
+		Ok(table)
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Species> {
+		self.species.get(name)
+	}
+
+	/// Returns the species the player can choose from, sorted by name.
+	pub fn playable(&self) -> Vec<&Species> {
+		let mut species: Vec<&Species> = self
+			.species
+			.values()
+			.filter(|species| species.has_flag(Flag::Playable))
+			.collect();
+		species.sort_by(|a, b| a.name.cmp(&b.name));
+		species
+	}
+
+	/// Returns the species for a character, e.g. the player or an NPC.
+	pub fn of(&self, store: &Store, name: &Subject) -> &Species {
+		let species = store
+			.lookup_str(name, Predicate::Species)
+			.unwrap_or_else(|| panic!("{} has no species", name));
+		self.species
+			.get(&species)
+			.unwrap_or_else(|| panic!("{} has unknown species {}", name, species))
+	}
+
+	fn add(&mut self, builder: Builder) -> Result<(), String> {
+		let species = builder.build()?;
+		self.species.insert(species.name.clone(), species);
+		Ok(())
+	}
+}
+
+/// Used by the unit tests to load data/species.txt.
+#[cfg(test)]
+pub fn test_species() -> std::rc::Rc<SpeciesTable> {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/species.txt");
+	std::rc::Rc::new(SpeciesTable::load(&path).unwrap())
+}
+
+// --- Private Items ----------------------------------------------------------
+struct Builder {
+	name: String,
+	line_num: usize,
+	symbol: Option<char>,
+	color: Option<Color>,
+	max_hps: Option<i32>,
+	move_scale: f32,
+	attacks: Vec<Attack>,
+	resistances: Vec<(Brand, i32)>,
+	flags: Vec<Flag>,
+	behavior: Option<Behavior>,
+	description: Option<String>,
+}
+
+impl Builder {
+	fn new(name: &str, line_num: usize) -> Builder {
+		Builder {
+			name: name.to_string(),
+			line_num,
+			symbol: None,
+			color: None,
+			max_hps: None,
+			move_scale: 1.0,
+			attacks: Vec::new(),
+			resistances: Vec::new(),
+			flags: Vec::new(),
+			behavior: None,
+			description: None,
+		}
+	}
+
+	fn set(&mut self, key: &str, args: &[&str]) -> Result<(), String> {
+		match (key, args) {
+			("symbol", [symbol]) => {
+				let mut chars = symbol.chars();
+				match (chars.next(), chars.next()) {
+					(Some(c), None) => self.symbol = Some(c),
+					_ => {
+						return Err(format!(
+							"expected a single character but found '{}'",
+							symbol
+						))
+					}
+				}
+			}
+			("color", [name]) => {
+				let color =
+					color::from_name(name).ok_or_else(|| format!("unknown color '{}'", name))?;
+				self.color = Some(color);
+			}
+			("hps", [hps]) => {
+				let hps = parse_int(hps)?;
+				if hps <= 0 {
+					return Err(format!("hps should be positive but found {}", hps));
+				}
+				self.max_hps = Some(hps);
+			}
+			("move_scale", [scale]) => {
+				self.move_scale = match scale.parse::<f32>() {
+					Ok(scale) if scale > 0.0 => scale,
+					_ => {
+						return Err(format!(
+							"move_scale should be a positive number but found '{}'",
+							scale
+						))
+					}
+				};
+			}
+			("attack", [verb, damage, brand]) => self.attacks.push(Attack {
+				verb: verb.to_string(),
+				damage: parse_int(damage)?,
+				brand: brand.parse()?,
+			}),
+			("resist", [brand, percent]) => {
+				let percent = parse_int(percent)?;
+				if !(0..=100).contains(&percent) {
+					return Err(format!(
+						"resist should be in [0, 100] but found {}",
+						percent
+					));
+				}
+				self.resistances.push((brand.parse()?, percent));
+			}
+			("flags", flags) => {
+				for flag in flags {
+					self.flags.push(match *flag {
+						"Airborne" => Flag::Airborne,
+						"Aquatic" => Flag::Aquatic,
+						"Playable" => Flag::Playable,
+						_ => {
+							return Err(format!(
+								"expected Airborne, Aquatic, or Playable but found '{}'",
+								flag
+							))
+						}
+					});
+				}
+			}
+			("behavior", [name]) => {
+				self.behavior = Some(match *name {
+					"Aggressive" => Behavior::Aggressive,
+					"Passive" => Behavior::Passive,
+					"Skittish" => Behavior::Skittish,
+					_ => {
+						return Err(format!(
+							"expected Aggressive, Passive, or Skittish but found '{}'",
+							name
+						))
+					}
+				});
+			}
+			("description", words) if !words.is_empty() => {
+				self.description = Some(words.join(" "));
+			}
+			("symbol", _) | ("color", _) | ("hps", _) | ("move_scale", _) | ("behavior", _) => {
+				return Err(format!("{} takes one argument", key))
+			}
+			("attack", _) => return Err("expected 'attack VERB DAMAGE BRAND'".to_string()),
+			("description", _) => return Err("expected 'description TEXT'".to_string()),
+			("resist", _) => return Err("expected 'resist BRAND PERCENT'".to_string()),
+			_ => return Err(format!("unknown attribute '{}'", key)),
+		}
+		Ok(())
+	}
+
+	fn build(self) -> Result<Species, String> {
+		let missing = |attribute| {
+			format!(
+				"{}: species {} is missing {}",
+				self.line_num, self.name, attribute
+			)
+		};
+		Ok(Species {
+			symbol: self.symbol.ok_or_else(|| missing("symbol"))?,
+			color: self.color.ok_or_else(|| missing("color"))?,
+			max_hps: self.max_hps.ok_or_else(|| missing("hps"))?,
+			behavior: self.behavior.ok_or_else(|| missing("behavior"))?,
+			move_scale: self.move_scale,
+			attacks: self.attacks,
+			resistances: self.resistances,
+			flags: self.flags,
+			description: self.description,
+			name: self.name,
+		})
+	}
+}
+
+fn parse_int(text: &str) -> Result<i32, String> {
+	text.parse()
+		.map_err(|_| format!("expected an integer but found '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_data_file() {
+		let table = test_species();
+		let ay = table.get("ay").unwrap();
+		assert_eq!(ay.symbol, 'a');
+		assert_eq!(ay.color, Color::BurlyWood);
+		assert_eq!(ay.max_hps, 14);
+		assert_eq!(ay.behavior, Behavior::Aggressive);
+		assert_eq!(ay.resistance(Brand::Cold), 50);
+		assert_eq!(ay.resistance(Brand::Fire), 0);
+		assert_eq!(
+			ay.attacks,
+			vec![Attack {
+				verb: "bite".to_string(),
+				damage: 10,
+				brand: Brand::Physical
+			}]
+		);
+		assert_eq!(
+			ay.description,
+			Some("A giant wolf that hunts in packs.".to_string())
+		);
+		assert!(table.get("human").is_some());
+		assert!(table.get("bhederin").is_some());
+		assert!(table.get("dragon").is_none());
+
+		let playable: Vec<&str> = table
+			.playable()
+			.iter()
+			.map(|species| species.name.as_str())
+			.collect();
+		assert_eq!(playable, vec!["human", "tiste-andii", "toblakai", "trell"]);
+	}
+
+	#[test]
+	fn test_parse() {
+		let text = "
+# comment
+species moranth  # trailing comment
+	symbol m
+	color Green
+	hps 30
+	move_scale 1.2
+	attack hit 8 Physical
+	attack burn 4 Fire
+	flags Airborne
+	behavior Passive
+";
+		let table = SpeciesTable::parse(text).unwrap();
+		let moranth = table.get("moranth").unwrap();
+		assert_eq!(moranth.move_scale, 1.2);
+		assert_eq!(moranth.attacks.len(), 2);
+		assert!(moranth.has_flag(Flag::Airborne));
+		assert!(!moranth.has_flag(Flag::Aquatic));
+		assert_eq!(moranth.description, None);
+	}
+
+	fn parse_err(text: &str) -> String {
+		SpeciesTable::parse(text).err().unwrap()
+	}
+
+	#[test]
+	fn test_errors() {
+		let species = "species ay\nsymbol a\ncolor Red\nhps 10\nbehavior Passive\n";
+		assert!(SpeciesTable::parse(species).is_ok());
+
+		assert_eq!(parse_err("symbol a\n"), "1: expected a species line");
+		assert_eq!(
+			parse_err("species ay\ncolor Burly\n"),
+			"2: unknown color 'Burly'"
+		);
+		assert_eq!(
+			parse_err("species ay\nhps ten\n"),
+			"2: expected an integer but found 'ten'"
+		);
+		assert_eq!(
+			parse_err("species ay\nsymbol ab\n"),
+			"2: expected a single character but found 'ab'"
+		);
+		assert_eq!(
+			parse_err("species ay\nattack bite 10 Acid\n"),
+			"2: expected Cold, Fire, Physical, or Poison but found 'Acid'"
+		);
+		assert_eq!(
+			parse_err("species ay\nresist Cold 150\n"),
+			"2: resist should be in [0, 100] but found 150"
+		);
+		assert_eq!(
+			parse_err("species ay\nwings 2\n"),
+			"2: unknown attribute 'wings'"
+		);
+		assert_eq!(
+			parse_err("\nspecies ay\nsymbol a\n"),
+			"2: species ay is missing color"
+		);
+		assert_eq!(
+			parse_err(&format!("{}{}", species, species)),
+			"6: species ay is already defined"
+		);
+	}
+}
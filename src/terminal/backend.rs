@@ -0,0 +1,66 @@
+//! Everything the UI draws and reads goes through a Backend so that it can run
+//! against something other than a real terminal, e.g. an in-memory grid in the
+//! unit tests.
+use super::super::core::*;
+use super::color::Color;
+use super::input::*;
+use super::view::*;
+use termion::event::Key;
+
+pub trait Backend {
+    /// Size of the screen in cells.
+    fn size(&self) -> Size;
+
+    /// Draws a cell at loc (which is zero based). Cells outside the screen are
+    /// ignored. Nothing is guaranteed to be visible until flush is called.
+    fn put_cell(&mut self, loc: Point, view: View);
+
+    /// Shows the cursor at loc or hides it if loc is None.
+    fn set_cursor(&mut self, loc: Option<Point>);
+
+    fn flush(&mut self);
+
+    /// Blocks until a key is pressed or the screen is resized. Returns None if
+    /// there will never be more input.
+    fn read_input(&mut self) -> Option<Input>;
+
+    /// Called when the game exits to put the terminal back the way it was.
+    fn restore(&mut self) {}
+
+    /// Like read_input except that resizes are skipped.
+    fn read_key(&mut self) -> Option<Key> {
+        loop {
+            match self.read_input() {
+                Some(Input::Key(key)) => return Some(key),
+                Some(Input::Resize) => (),
+                None => return None,
+            }
+        }
+    }
+
+    /// Blanks out the entire screen.
+    fn clear(&mut self) {
+        let size = self.size();
+        for y in 0..size.height {
+            for x in 0..size.width {
+                self.put_cell(Point::new(x, y), View::blank());
+            }
+        }
+    }
+
+    /// Draws text starting at loc. Characters past the edge of the screen are
+    /// dropped.
+    fn put_str(&mut self, loc: Point, fg: Color, bg: Color, text: &str) {
+        for (i, symbol) in text.chars().enumerate() {
+            let loc = Point::new(loc.x + i as i32, loc.y);
+            self.put_cell(loc, View { symbol, fg, bg });
+        }
+    }
+
+    /// Draws text at the start of line y and fills the rest of the line with bg.
+    fn put_line(&mut self, y: i32, fg: Color, bg: Color, text: &str) {
+        let width = self.size().width as usize;
+        let line = format!("{:width$.width$}", text, width = width);
+        self.put_str(Point::new(0, y), fg, bg, &line);
+    }
+}
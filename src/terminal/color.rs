@@ -1,10 +1,8 @@
-use termion;
-
 // These are the X11 color names (from http://cng.seas.rochester.edu/CNG/docs/x11color.html).
 // In general we work with the X11 colors instead of AnsiColors because the X11 colors are
 // a lot nicer to deal with.
 #[allow(dead_code)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Color {
 	LightPink,
 	Pink,
@@ -148,6 +146,153 @@ pub enum Color {
 	Black,
 }
 
+/// Returns the color with the X11 name, e.g. "BurlyWood".
+pub fn from_name(name: &str) -> Option<Color> {
+	match name {
+		"LightPink" => Some(Color::LightPink),
+		"Pink" => Some(Color::Pink),
+		"Crimson" => Some(Color::Crimson),
+		"LavenderBlush" => Some(Color::LavenderBlush),
+		"PaleVioletRed" => Some(Color::PaleVioletRed),
+		"HotPink" => Some(Color::HotPink),
+		"DeepPink" => Some(Color::DeepPink),
+		"MediumVioletRed" => Some(Color::MediumVioletRed),
+		"Orchid" => Some(Color::Orchid),
+		"Thistle" => Some(Color::Thistle),
+		"Plum" => Some(Color::Plum),
+		"Violet" => Some(Color::Violet),
+		"Magenta" => Some(Color::Magenta),
+		"Fuchsia" => Some(Color::Fuchsia),
+		"DarkMagenta" => Some(Color::DarkMagenta),
+		"Purple" => Some(Color::Purple),
+		"MediumOrchid" => Some(Color::MediumOrchid),
+		"DarkViolet" => Some(Color::DarkViolet),
+		"DarkOrchid" => Some(Color::DarkOrchid),
+		"Indigo" => Some(Color::Indigo),
+		"BlueViolet" => Some(Color::BlueViolet),
+		"MediumPurple" => Some(Color::MediumPurple),
+		"MediumSlateBlue" => Some(Color::MediumSlateBlue),
+		"SlateBlue" => Some(Color::SlateBlue),
+		"DarkSlateBlue" => Some(Color::DarkSlateBlue),
+		"Lavender" => Some(Color::Lavender),
+		"GhostWhite" => Some(Color::GhostWhite),
+		"Blue" => Some(Color::Blue),
+		"MediumBlue" => Some(Color::MediumBlue),
+		"MidnightBlue" => Some(Color::MidnightBlue),
+		"DarkBlue" => Some(Color::DarkBlue),
+		"Navy" => Some(Color::Navy),
+		"RoyalBlue" => Some(Color::RoyalBlue),
+		"CornflowerBlue" => Some(Color::CornflowerBlue),
+		"LightSteelBlue" => Some(Color::LightSteelBlue),
+		"LightSlateGray" => Some(Color::LightSlateGray),
+		"SlateGray" => Some(Color::SlateGray),
+		"DodgerBlue" => Some(Color::DodgerBlue),
+		"AliceBlue" => Some(Color::AliceBlue),
+		"SteelBlue" => Some(Color::SteelBlue),
+		"LightSkyBlue" => Some(Color::LightSkyBlue),
+		"SkyBlue" => Some(Color::SkyBlue),
+		"DeepSkyBlue" => Some(Color::DeepSkyBlue),
+		"LightBlue" => Some(Color::LightBlue),
+		"PowderBlue" => Some(Color::PowderBlue),
+		"CadetBlue" => Some(Color::CadetBlue),
+		"Azure" => Some(Color::Azure),
+		"LightCyan" => Some(Color::LightCyan),
+		"PaleTurquoise" => Some(Color::PaleTurquoise),
+		"Cyan" => Some(Color::Cyan),
+		"Aqua" => Some(Color::Aqua),
+		"DarkTurquoise" => Some(Color::DarkTurquoise),
+		"DarkSlateGray" => Some(Color::DarkSlateGray),
+		"DarkCyan" => Some(Color::DarkCyan),
+		"Teal" => Some(Color::Teal),
+		"MediumTurquoise" => Some(Color::MediumTurquoise),
+		"LightSeaGreen" => Some(Color::LightSeaGreen),
+		"Turquoise" => Some(Color::Turquoise),
+		"Aquamarine" => Some(Color::Aquamarine),
+		"MediumAquamarine" => Some(Color::MediumAquamarine),
+		"MediumSpringGreen" => Some(Color::MediumSpringGreen),
+		"MintCream" => Some(Color::MintCream),
+		"SpringGreen" => Some(Color::SpringGreen),
+		"MediumSeaGreen" => Some(Color::MediumSeaGreen),
+		"SeaGreen" => Some(Color::SeaGreen),
+		"Honeydew" => Some(Color::Honeydew),
+		"LightGreen" => Some(Color::LightGreen),
+		"PaleGreen" => Some(Color::PaleGreen),
+		"DarkSeaGreen" => Some(Color::DarkSeaGreen),
+		"LimeGreen" => Some(Color::LimeGreen),
+		"Lime" => Some(Color::Lime),
+		"ForestGreen" => Some(Color::ForestGreen),
+		"Green" => Some(Color::Green),
+		"DarkGreen" => Some(Color::DarkGreen),
+		"Chartreuse" => Some(Color::Chartreuse),
+		"LawnGreen" => Some(Color::LawnGreen),
+		"GreenYellow" => Some(Color::GreenYellow),
+		"DarkOliveGreen" => Some(Color::DarkOliveGreen),
+		"YellowGreen" => Some(Color::YellowGreen),
+		"OliveDrab" => Some(Color::OliveDrab),
+		"Beige" => Some(Color::Beige),
+		"LightGoldenrodYellow" => Some(Color::LightGoldenrodYellow),
+		"Ivory" => Some(Color::Ivory),
+		"LightYellow" => Some(Color::LightYellow),
+		"Yellow" => Some(Color::Yellow),
+		"Olive" => Some(Color::Olive),
+		"DarkKhaki" => Some(Color::DarkKhaki),
+		"LemonChiffon" => Some(Color::LemonChiffon),
+		"PaleGoldenrod" => Some(Color::PaleGoldenrod),
+		"Khaki" => Some(Color::Khaki),
+		"Gold" => Some(Color::Gold),
+		"Cornsilk" => Some(Color::Cornsilk),
+		"Goldenrod" => Some(Color::Goldenrod),
+		"DarkGoldenrod" => Some(Color::DarkGoldenrod),
+		"FloralWhite" => Some(Color::FloralWhite),
+		"OldLace" => Some(Color::OldLace),
+		"Wheat" => Some(Color::Wheat),
+		"Moccasin" => Some(Color::Moccasin),
+		"Orange" => Some(Color::Orange),
+		"PapayaWhip" => Some(Color::PapayaWhip),
+		"BlanchedAlmond" => Some(Color::BlanchedAlmond),
+		"NavajoWhite" => Some(Color::NavajoWhite),
+		"AntiqueWhite" => Some(Color::AntiqueWhite),
+		"Tan" => Some(Color::Tan),
+		"BurlyWood" => Some(Color::BurlyWood),
+		"Bisque" => Some(Color::Bisque),
+		"DarkOrange" => Some(Color::DarkOrange),
+		"Linen" => Some(Color::Linen),
+		"Peru" => Some(Color::Peru),
+		"PeachPuff" => Some(Color::PeachPuff),
+		"SandyBrown" => Some(Color::SandyBrown),
+		"Chocolate" => Some(Color::Chocolate),
+		"SaddleBrown" => Some(Color::SaddleBrown),
+		"Seashell" => Some(Color::Seashell),
+		"Sienna" => Some(Color::Sienna),
+		"LightSalmon" => Some(Color::LightSalmon),
+		"Coral" => Some(Color::Coral),
+		"OrangeRed" => Some(Color::OrangeRed),
+		"DarkSalmon" => Some(Color::DarkSalmon),
+		"Tomato" => Some(Color::Tomato),
+		"MistyRose" => Some(Color::MistyRose),
+		"Salmon" => Some(Color::Salmon),
+		"Snow" => Some(Color::Snow),
+		"LightCoral" => Some(Color::LightCoral),
+		"RosyBrown" => Some(Color::RosyBrown),
+		"IndianRed" => Some(Color::IndianRed),
+		"Red" => Some(Color::Red),
+		"Brown" => Some(Color::Brown),
+		"FireBrick" => Some(Color::FireBrick),
+		"DarkRed" => Some(Color::DarkRed),
+		"Maroon" => Some(Color::Maroon),
+		"White" => Some(Color::White),
+		"WhiteSmoke" => Some(Color::WhiteSmoke),
+		"Gainsboro" => Some(Color::Gainsboro),
+		"LightGrey" => Some(Color::LightGrey),
+		"Silver" => Some(Color::Silver),
+		"DarkGray" => Some(Color::DarkGray),
+		"Gray" => Some(Color::Gray),
+		"DimGray" => Some(Color::DimGray),
+		"Black" => Some(Color::Black),
+		_ => None,
+	}
+}
+
 // See https://camo.githubusercontent.com/18622d6a234413cbc0aba27a09146797bf1eef4d/68747470733a2f2f692e696d6775722e636f6d2f4b696c72306d432e706e673f31
 // and http://cng.seas.rochester.edu/CNG/docs/x11color.html
 pub fn to_termion(color: Color) -> termion::color::AnsiValue {
@@ -0,0 +1,266 @@
+//! The part of the terminal that shows the game's text output: a pane at the
+//! bottom of the screen with the latest messages and a full screen scrollback
+//! viewer.
+use super::super::core::*;
+use super::super::messages::*;
+use super::super::player::*;
+use super::backend::*;
+use super::color::Color;
+use super::keys::*;
+use super::layout::*;
+use termion::event::Key;
+
+/// Number of screen lines used by the message pane.
+pub const MESSAGE_LINES: i32 = 5;
+
+/// Renders the latest messages at the bottom of the screen.
+pub fn render_messages(backend: &mut dyn Backend, store: &Store, layout: &Layout) {
+    let width = layout.size.width as usize;
+    let messages = recent_messages(store, MESSAGE_LINES);
+    let lines = wrap_messages(&messages, width, false);
+    let lines = last_lines(&lines, MESSAGE_LINES as usize);
+
+    // Messages are bottom aligned so blank out any unused lines at the top.
+    let blanks = MESSAGE_LINES as usize - lines.len();
+    for (i, (topic, text)) in lines.iter().enumerate() {
+        let y = layout.messages_y + (blanks + i) as i32;
+        backend.put_line(y, topic_to_color(*topic), Color::Black, text);
+    }
+}
+
+/// Full screen view of all the retained messages.
+pub struct Scrollback {
+    lines: Vec<(Topic, String)>,
+    top: usize,  // index of the first visible line
+    page: usize, // number of visible lines
+}
+
+impl Scrollback {
+    /// Starts out scrolled to the most recent message.
+    pub fn new(store: &Store, terminal_size: Size) -> Scrollback {
+        let lines = wrap_messages(&messages(store), terminal_size.width as usize, true);
+        let page = (terminal_size.height - 1).max(1) as usize; // last line is help text
+        let top = lines.len().saturating_sub(page);
+        Scrollback { lines, top, page }
+    }
+
+    /// Returns false if the viewer should be closed.
+    pub fn on_key(&mut self, key: Key, keys: &KeyBindings) -> bool {
+        let max_top = self.lines.len().saturating_sub(self.page);
+        match (key, keys.action(key)) {
+            (_, Some(PlayerAction::DeltaNorth)) => self.top = self.top.saturating_sub(1),
+            (_, Some(PlayerAction::DeltaSouth)) => self.top = (self.top + 1).min(max_top),
+            (_, Some(PlayerAction::ShowMessages)) | (_, Some(PlayerAction::Quit)) => return false,
+            (Key::PageUp, _) => self.top = self.top.saturating_sub(self.page),
+            (Key::PageDown, _) => self.top = (self.top + self.page).min(max_top),
+            (Key::Home, _) => self.top = 0,
+            (Key::End, _) => self.top = max_top,
+            (Key::Esc, _) => return false,
+            _ => (),
+        }
+        true
+    }
+
+    pub fn render(&self, backend: &mut dyn Backend) {
+        backend.clear();
+        for (i, (topic, text)) in self.visible().iter().enumerate() {
+            backend.put_line(i as i32, topic_to_color(*topic), Color::Black, text);
+        }
+        backend.put_line(
+            backend.size().height - 1,
+            Color::Gray,
+            Color::Black,
+            "Up/Down/PageUp/PageDown/Home/End to scroll, Esc to close",
+        );
+        backend.flush();
+    }
+
+    fn visible(&self) -> &[(Topic, String)] {
+        let end = (self.top + self.page).min(self.lines.len());
+        &self.lines[self.top..end]
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+// TODO: Should probably have a config option for console colors.
+fn topic_to_color(topic: Topic) -> Color {
+    match topic {
+        Topic::Error => Color::Red,
+        Topic::NonGamePlay => Color::Lime,
+        Topic::NpcIsDamaged => Color::Green,
+        Topic::NpcIsNotDamaged => Color::LightGreen,
+        Topic::PlayerDidDamage => Color::AliceBlue,
+        Topic::PlayerDidNoDamage => Color::Gray,
+        Topic::PlayerIsDamaged => Color::Red,
+        Topic::PlayerIsNotDamaged => Color::Orange,
+        Topic::PlayerIsImpaired => Color::Orange,
+        Topic::PlayerIsThreatened => Color::Orange,
+        Topic::Warning => Color::Orange,
+    }
+}
+
+/// Splits the messages into screen lines, optionally prefixing each message
+/// with the time it was added.
+fn wrap_messages(messages: &[Message], width: usize, with_time: bool) -> Vec<(Topic, String)> {
+    let mut lines = Vec::new();
+    for message in messages.iter() {
+        let text = if with_time {
+            format!("{} {}", message.time, message.text)
+        } else {
+            message.text.clone()
+        };
+        for line in split_output(width, &text) {
+            lines.push((message.topic, line));
+        }
+    }
+    lines
+}
+
+fn last_lines(lines: &[(Topic, String)], count: usize) -> &[(Topic, String)] {
+    &lines[lines.len().saturating_sub(count)..]
+}
+
+fn split_output(width: usize, line: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    let mut words: Vec<String> = line
+        .split_whitespace()
+        .map(|word| word.to_string())
+        .collect();
+    if words.is_empty() {
+        lines.push(String::new());
+        return lines;
+    }
+
+    // The first line is always full width so we have to special case it.
+    let line = collect_line(&mut words, width);
+    lines.push(line);
+
+    let indent = "   ".to_string();
+    while !words.is_empty() {
+        let line = collect_line(&mut words, width - indent.len());
+        lines.push(indent.clone() + &line);
+    }
+
+    lines
+}
+
+fn collect_line(words: &mut Vec<String>, width: usize) -> String {
+    let mut line = String::new();
+
+    loop {
+        if line.len() + words[0].len() > width {
+            line += &words[0][0..(width - line.len())];
+        } else {
+            line += &words[0];
+        }
+        words.remove(0);
+
+        if words.is_empty() || line.len() + words[0].len() >= width {
+            break;
+        }
+        line += " ";
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_word() {
+        let actual = split_output(4, "abc");
+        let expected = vec!["abc".to_string()];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_exact_word() {
+        let actual = split_output(4, "abcd");
+        let expected = vec!["abcd".to_string()];
+        assert_eq!(actual, expected);
+    }
+
+    // We don't handle this case terribly well but that should be OK in practice.
+    #[test]
+    fn test_long_word() {
+        let actual = split_output(4, "abcde");
+        let expected = vec!["abcd".to_string()];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_short_line() {
+        let actual = split_output(6, "abc");
+        let expected = vec!["abc".to_string()];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_exact_line() {
+        let actual = split_output(5, "ab cd");
+        let expected = vec!["ab cd".to_string()];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_really_long_line() {
+        let actual = split_output(12, "The quick brown fox jumped over the lazy dog.");
+        let expected = vec![
+            "The quick".to_string(),
+            "   brown fox".to_string(),
+            "   jumped".to_string(),
+            "   over the".to_string(),
+            "   lazy dog.".to_string(),
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    fn message(text: &str, secs: f32) -> Message {
+        Message {
+            topic: Topic::Warning,
+            text: text.to_string(),
+            time: Time::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn test_wrap_messages() {
+        let messages = vec![message("You hit the ay.", 1.0), message("Ouch", 2.5)];
+        let lines = wrap_messages(&messages, 10, true);
+        let texts: Vec<&str> = lines.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(texts, vec!["1.0s You", "   hit the", "   ay.", "2.5s Ouch"]);
+        assert_eq!(last_lines(&lines, 2).len(), 2);
+        assert_eq!(last_lines(&lines, 10).len(), 4);
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let keys = test_bindings();
+        let lines: Vec<(Topic, String)> = (0..10)
+            .map(|i| (Topic::Warning, format!("line {}", i)))
+            .collect();
+        let mut scrollback = Scrollback {
+            lines,
+            top: 6,
+            page: 4,
+        };
+        assert_eq!(scrollback.visible()[0].1, "line 6");
+
+        assert!(scrollback.on_key(Key::Down, &keys));
+        assert_eq!(scrollback.top, 6, "can't scroll past the end");
+        assert!(scrollback.on_key(Key::PageUp, &keys));
+        assert_eq!(scrollback.top, 2);
+        assert!(scrollback.on_key(Key::PageUp, &keys));
+        assert_eq!(scrollback.top, 0);
+        assert!(scrollback.on_key(Key::Down, &keys));
+        assert_eq!(scrollback.top, 1);
+        assert!(scrollback.on_key(Key::End, &keys));
+        assert_eq!(scrollback.visible().len(), 4);
+        assert!(scrollback.on_key(Key::Char('k'), &keys));
+        assert_eq!(scrollback.top, 5);
+        assert!(!scrollback.on_key(Key::Esc, &keys));
+    }
+}
@@ -0,0 +1,172 @@
+//! Double buffering for the screen. Each frame is rendered into a back buffer of
+//! Views which is compared against what was previously drawn so that only the
+//! cells that changed are written to the terminal. This matters for slow links,
+//! e.g. playing over ssh.
+use super::super::core::*;
+use super::color;
+use super::view::*;
+use std::io::Write;
+
+/// What we think is currently drawn on the screen.
+pub struct FrameBuffer {
+    size: Size,
+    front: Vec<Option<View>>, // None if we don't know what is on the screen
+}
+
+impl FrameBuffer {
+    pub fn new() -> FrameBuffer {
+        FrameBuffer {
+            size: Size::new(0, 0),
+            front: Vec::new(),
+        }
+    }
+
+    /// Forces the next render to redraw everything, e.g. after the screen has
+    /// been cleared.
+    pub fn invalidate(&mut self) {
+        for view in self.front.iter_mut() {
+            *view = None;
+        }
+    }
+
+    /// Draws the cells in back (which is row major with size.width columns) that
+    /// differ from the last frame. Adjacent changed cells are written as a run
+    /// with a single cursor move and colors are only written when they change.
+    pub fn render<W: Write>(&mut self, out: &mut W, back: &[View], size: Size) {
+        assert_eq!(back.len(), (size.width * size.height) as usize);
+        if size != self.size {
+            self.size = size;
+            self.front = vec![None; back.len()];
+        }
+
+        // Other parts of the screen are drawn in between frames so we can't
+        // assume anything about the current colors.
+        let mut fg = None;
+        let mut bg = None;
+        for y in 0..size.height {
+            let mut cursor_x = None; // where the terminal's cursor is within this line
+            for x in 0..size.width {
+                let index = (x + y * size.width) as usize;
+                let view = back[index];
+                if self.front[index] == Some(view) {
+                    continue;
+                }
+
+                if cursor_x != Some(x) {
+                    // termion is 1-based
+                    let goto = termion::cursor::Goto((x + 1) as u16, (y + 1) as u16);
+                    let _ = write!(out, "{}", goto);
+                }
+                if bg != Some(view.bg) {
+                    let _ = write!(out, "{}", termion::color::Bg(color::to_termion(view.bg)));
+                    bg = Some(view.bg);
+                }
+                if fg != Some(view.fg) {
+                    let _ = write!(out, "{}", termion::color::Fg(color::to_termion(view.fg)));
+                    fg = Some(view.fg);
+                }
+                let _ = write!(out, "{}", view.symbol);
+                cursor_x = Some(x + 1);
+                self.front[index] = Some(view);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::Color;
+    use termion::cursor::Goto;
+
+    fn view(symbol: char, fg: Color, bg: Color) -> View {
+        View { symbol, fg, bg }
+    }
+
+    fn fg(color: Color) -> termion::color::Fg<termion::color::AnsiValue> {
+        termion::color::Fg(color::to_termion(color))
+    }
+
+    fn bg(color: Color) -> termion::color::Bg<termion::color::AnsiValue> {
+        termion::color::Bg(color::to_termion(color))
+    }
+
+    fn render(frame: &mut FrameBuffer, back: &[View], size: Size) -> String {
+        let mut out = Vec::new();
+        frame.render(&mut out, back, size);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_first_frame() {
+        let mut frame = FrameBuffer::new();
+        let size = Size::new(3, 2);
+        let back = vec![
+            view('#', Color::Red, Color::Black),
+            view('#', Color::Red, Color::Black),
+            view('.', Color::Gray, Color::Black),
+            view('~', Color::Gray, Color::Blue),
+            view('~', Color::Gray, Color::Blue),
+            view('~', Color::Gray, Color::Blue),
+        ];
+        let expected = format!(
+            "{}{}{}##{}.{}{}~~~",
+            Goto(1, 1),
+            bg(Color::Black),
+            fg(Color::Red),
+            fg(Color::Gray),
+            Goto(1, 2),
+            bg(Color::Blue),
+        );
+        assert_eq!(render(&mut frame, &back, size), expected);
+        assert_eq!(render(&mut frame, &back, size), "", "nothing changed");
+    }
+
+    #[test]
+    fn test_changed_cells() {
+        let mut frame = FrameBuffer::new();
+        let size = Size::new(4, 1);
+        let mut back = vec![view('.', Color::Gray, Color::Black); 4];
+        render(&mut frame, &back, size);
+
+        back[1] = view('@', Color::Yellow, Color::Black);
+        back[2] = view('.', Color::Gray, Color::Black);
+        back[3] = view('a', Color::Yellow, Color::Black);
+        let expected = format!(
+            "{}{}{}@{}a",
+            Goto(2, 1),
+            bg(Color::Black),
+            fg(Color::Yellow),
+            Goto(4, 1),
+        );
+        assert_eq!(render(&mut frame, &back, size), expected);
+
+        back[2] = view('@', Color::Yellow, Color::Black);
+        back[3] = view('@', Color::Yellow, Color::Black);
+        let expected = format!("{}{}{}@@", Goto(3, 1), bg(Color::Black), fg(Color::Yellow));
+        assert_eq!(
+            render(&mut frame, &back, size),
+            expected,
+            "adjacent cells are coalesced"
+        );
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let mut frame = FrameBuffer::new();
+        let size = Size::new(2, 2);
+        let back = vec![view('.', Color::Gray, Color::Black); 4];
+        let all = render(&mut frame, &back, size);
+
+        frame.invalidate();
+        assert_eq!(render(&mut frame, &back, size), all);
+
+        let size = Size::new(1, 4);
+        let expected = render(&mut FrameBuffer::new(), &back, size);
+        assert_eq!(
+            render(&mut frame, &back, size),
+            expected,
+            "resizing redraws everything"
+        );
+    }
+}
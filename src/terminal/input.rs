@@ -0,0 +1,187 @@
+//! Input normally comes from the keyboard but it can also come from a script,
+//! e.g. to reproduce a bug or for automated play testing.
+use super::keys::parse_key;
+use signal_hook::consts::SIGWINCH;
+use signal_hook::iterator::Signals;
+use std::collections::VecDeque;
+use std::iter::FromIterator;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use termion::event::Key;
+use termion::input::TermRead;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Key(Key),
+
+    /// The terminal was resized (SIGWINCH).
+    Resize,
+}
+
+pub trait InputSource {
+    /// Blocks until a key is pressed or the terminal is resized. Returns None
+    /// if there will never be more input.
+    fn next_input(&mut self) -> Option<Input>;
+}
+
+/// Reads keys from stdin. Key presses and terminal resizes are delivered
+/// through a single channel so that the screen can be redrawn when the
+/// terminal is resized while we're blocked waiting for a key.
+pub struct InputReader {
+    receiver: Receiver<Input>,
+}
+
+impl InputReader {
+    pub fn new() -> InputReader {
+        let (sender, receiver) = mpsc::channel();
+
+        let key_sender = sender.clone();
+        thread::spawn(move || {
+            for key in std::io::stdin().keys() {
+                match key {
+                    Ok(key) if key_sender.send(Input::Key(key)).is_ok() => (),
+                    _ => break,
+                }
+            }
+        });
+
+        // If we can't register for SIGWINCH the game is still playable, the
+        // screen just won't be redrawn until the next key press.
+        if let Ok(mut signals) = Signals::new([SIGWINCH]) {
+            thread::spawn(move || {
+                for _ in signals.forever() {
+                    if sender.send(Input::Resize).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        InputReader { receiver }
+    }
+}
+
+impl InputSource for InputReader {
+    fn next_input(&mut self) -> Option<Input> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Input that was queued up ahead of time, e.g. from a script file or an
+/// iterator of keys.
+#[derive(Default)]
+pub struct ScriptedInput {
+    inputs: VecDeque<Input>,
+}
+
+impl ScriptedInput {
+    pub fn load(path: &Path) -> Result<ScriptedInput, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        ScriptedInput::parse(&text).map_err(|err| format!("{}:{}", path.display(), err))
+    }
+
+    /// Scripts are whitespace separated key names (as in data/keys.txt) with
+    /// an optional repeat count, e.g. "n K a l Enter 6*3". Everything after a
+    /// '#' is a comment. Errors are prefixed with the line number.
+    pub fn parse(text: &str) -> Result<ScriptedInput, String> {
+        let mut inputs = VecDeque::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            for token in line.split_whitespace() {
+                let (name, count) = split_count(token);
+                let key = parse_key(name).map_err(|err| format!("{}: {}", index + 1, err))?;
+                for _ in 0..count {
+                    inputs.push_back(Input::Key(key));
+                }
+            }
+        }
+        Ok(ScriptedInput { inputs })
+    }
+
+    #[cfg(test)]
+    pub fn push(&mut self, input: Input) {
+        self.inputs.push_back(input);
+    }
+}
+
+impl FromIterator<Key> for ScriptedInput {
+    fn from_iter<I: IntoIterator<Item = Key>>(keys: I) -> Self {
+        let inputs = keys.into_iter().map(Input::Key).collect();
+        ScriptedInput { inputs }
+    }
+}
+
+impl Extend<Key> for ScriptedInput {
+    fn extend<I: IntoIterator<Item = Key>>(&mut self, keys: I) {
+        self.inputs.extend(keys.into_iter().map(Input::Key));
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn next_input(&mut self) -> Option<Input> {
+        self.inputs.pop_front()
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+/// Splits "6*3" into ("6", 3). Note that "*" and "**2" are both the '*' key.
+fn split_count(token: &str) -> (&str, usize) {
+    if let Some(index) = token.rfind('*') {
+        let (name, count) = (&token[..index], &token[index + 1..]);
+        if !name.is_empty() {
+            if let Ok(count) = count.parse() {
+                return (name, count);
+            }
+        }
+    }
+    (token, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(script: &str) -> Result<Vec<Key>, String> {
+        let mut input = ScriptedInput::parse(script)?;
+        let mut keys = Vec::new();
+        while let Some(Input::Key(key)) = input.next_input() {
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    #[test]
+    fn test_script() {
+        assert_eq!(
+            keys("n K a # name\n\nEnter 6*3 **2 *").unwrap(),
+            vec![
+                Key::Char('n'),
+                Key::Char('K'),
+                Key::Char('a'),
+                Key::Char('\n'),
+                Key::Char('6'),
+                Key::Char('6'),
+                Key::Char('6'),
+                Key::Char('*'),
+                Key::Char('*'),
+                Key::Char('*'),
+            ]
+        );
+        assert_eq!(keys("Up*0").unwrap(), vec![]);
+        assert_eq!(
+            keys("k\nHyper-k"),
+            Err("2: unknown key 'Hyper-k'".to_string())
+        );
+        assert_eq!(keys("6*x"), Err("1: unknown key '6*x'".to_string()));
+
+        let mut input: ScriptedInput = vec![Key::Up].into_iter().collect();
+        input.extend(vec![Key::Down]);
+        input.push(Input::Resize);
+        assert_eq!(input.next_input(), Some(Input::Key(Key::Up)));
+        assert_eq!(input.next_input(), Some(Input::Key(Key::Down)));
+        assert_eq!(input.next_input(), Some(Input::Resize));
+        assert_eq!(input.next_input(), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Key bindings are defined in a data file (see data/keys.txt) so that players
+//! can use vi-keys, the numeric keypad, or their own layout.
+use super::super::player::*;
+use super::backend::*;
+use super::color::Color;
+use fnv::FnvHashMap;
+use std::path::Path;
+use termion::event::Key;
+
+pub struct KeyBindings {
+    actions: FnvHashMap<Key, PlayerAction>,
+}
+
+impl KeyBindings {
+    pub fn load(path: &Path) -> Result<KeyBindings, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        KeyBindings::parse(&text).map_err(|err| format!("{}:{}", path.display(), err))
+    }
+
+    /// Errors are prefixed with the line number, e.g. "12: 'k' is bound to
+    /// both DeltaNorth and Quit".
+    pub fn parse(text: &str) -> Result<KeyBindings, String> {
+        let mut actions: FnvHashMap<Key, PlayerAction> = FnvHashMap::default();
+        for (index, line) in text.lines().enumerate() {
+            let line_num = index + 1;
+            let line = line.split('#').next().unwrap();
+            let mut tokens = line.split_whitespace();
+            let name = match tokens.next() {
+                Some(name) => name,
+                None => continue,
+            };
+            let action = ACTIONS
+                .iter()
+                .find(|action| format!("{:?}", action) == name)
+                .ok_or_else(|| format!("{}: unknown action '{}'", line_num, name))?;
+
+            let keys: Vec<&str> = tokens.collect();
+            if keys.is_empty() {
+                return Err(format!("{}: {} isn't bound to any keys", line_num, name));
+            }
+            for text in keys {
+                let key = parse_key(text).map_err(|err| format!("{}: {}", line_num, err))?;
+                match actions.get(&key) {
+                    Some(old) if old != action => {
+                        return Err(format!(
+                            "{}: '{}' is bound to both {:?} and {:?}",
+                            line_num, text, old, action
+                        ))
+                    }
+                    _ => {
+                        actions.insert(key, *action);
+                    }
+                }
+            }
+        }
+
+        // Otherwise the player would have no way to exit the game.
+        if !actions.values().any(|action| *action == PlayerAction::Quit) {
+            return Err("Quit isn't bound to a key".to_string());
+        }
+        Ok(KeyBindings { actions })
+    }
+
+    pub fn action(&self, key: Key) -> Option<PlayerAction> {
+        self.actions.get(&key).copied()
+    }
+
+    /// Returns one of the keys bound to action (the same one each time).
+    pub fn key_for(&self, action: PlayerAction) -> Option<Key> {
+        self.actions
+            .iter()
+            .filter(|(_, a)| **a == action)
+            .map(|(key, _)| *key)
+            .min_by_key(|key| key_name(*key))
+    }
+
+    /// Returns a line for each bound action with its description and keys.
+    pub fn help_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for action in ACTIONS.iter() {
+            let mut names: Vec<String> = self
+                .actions
+                .iter()
+                .filter(|(_, a)| *a == action)
+                .map(|(key, _)| key_name(*key))
+                .collect();
+            if !names.is_empty() {
+                names.sort();
+                lines.push(format!(
+                    "{:<24}{}",
+                    describe_action(*action),
+                    names.join(" ")
+                ));
+            }
+        }
+        lines
+    }
+}
+
+/// Used by the unit tests to load data/keys.txt.
+#[cfg(test)]
+pub fn test_bindings() -> KeyBindings {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/keys.txt");
+    KeyBindings::load(&path).unwrap()
+}
+
+/// Full screen list of the key bindings.
+pub fn render_help(backend: &mut dyn Backend, keys: &KeyBindings) {
+    backend.clear();
+    for (i, line) in keys.help_lines().iter().enumerate() {
+        backend.put_line(i as i32, Color::White, Color::Black, line);
+    }
+    let y = backend.size().height - 1;
+    backend.put_line(y, Color::Gray, Color::Black, "Press any key to continue.");
+    backend.flush();
+}
+
+/// Returns the name used for the key in data/keys.txt, e.g. "Ctrl-s".
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Esc => "Esc".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char('#') => "Hash".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::Ctrl(c) => format!("Ctrl-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Char(c) => c.to_string(),
+        _ => format!("{:?}", key),
+    }
+}
+
+/// Parses a key name as used in data/keys.txt, e.g. "Ctrl-s".
+pub fn parse_key(text: &str) -> Result<Key, String> {
+    let mut chars = text.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Char(c));
+    }
+    let key = match text {
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Enter" => Key::Char('\n'),
+        "Esc" => Key::Esc,
+        "Tab" => Key::Char('\t'),
+        "Space" => Key::Char(' '),
+        "Hash" => Key::Char('#'), // a bare # starts a comment
+        "Backspace" => Key::Backspace,
+        "Delete" => Key::Delete,
+        "Insert" => Key::Insert,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        _ => {
+            if let Some(rest) = text.strip_prefix("Ctrl-") {
+                let mut chars = rest.chars();
+                if let (Some(c), None) = (chars.next(), chars.next()) {
+                    return Ok(Key::Ctrl(c));
+                }
+            } else if let Some(rest) = text.strip_prefix('F') {
+                if let Ok(n) = rest.parse::<u8>() {
+                    if (1..=12).contains(&n) {
+                        return Ok(Key::F(n));
+                    }
+                }
+            }
+            return Err(format!("unknown key '{}'", text));
+        }
+    };
+    Ok(key)
+}
+
+// --- Private Items ----------------------------------------------------------
+/// Actions that can be bound to keys in the order they appear in help.
+const ACTIONS: [PlayerAction; 13] = [
+    PlayerAction::DeltaNorth,
+    PlayerAction::DeltaSouth,
+    PlayerAction::DeltaWest,
+    PlayerAction::DeltaEast,
+    PlayerAction::DeltaNorthWest,
+    PlayerAction::DeltaNorthEast,
+    PlayerAction::DeltaSouthWest,
+    PlayerAction::DeltaSouthEast,
+    PlayerAction::Look,
+    PlayerAction::ShowMessages,
+    PlayerAction::Help,
+    PlayerAction::SaveGame,
+    PlayerAction::Quit,
+];
+
+fn describe_action(action: PlayerAction) -> &'static str {
+    match action {
+        PlayerAction::DeltaEast => "Move east",
+        PlayerAction::DeltaNorth => "Move north",
+        PlayerAction::DeltaNorthEast => "Move northeast",
+        PlayerAction::DeltaNorthWest => "Move northwest",
+        PlayerAction::DeltaSouth => "Move south",
+        PlayerAction::DeltaSouthEast => "Move southeast",
+        PlayerAction::DeltaSouthWest => "Move southwest",
+        PlayerAction::DeltaWest => "Move west",
+        PlayerAction::Help => "Show this help",
+        PlayerAction::Look => "Examine the map",
+        PlayerAction::Quit => "Save and quit",
+        PlayerAction::SaveGame => "Save the game",
+        PlayerAction::ShowMessages => "Show old messages",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_file() {
+        let keys = test_bindings();
+        assert_eq!(keys.action(Key::Up), Some(PlayerAction::DeltaNorth));
+        assert_eq!(keys.action(Key::Char('8')), Some(PlayerAction::DeltaNorth));
+        assert_eq!(keys.action(Key::Char('k')), Some(PlayerAction::DeltaNorth));
+        assert_eq!(keys.action(Key::Ctrl('s')), Some(PlayerAction::SaveGame));
+        assert_eq!(keys.action(Key::Char('Q')), None);
+    }
+
+    #[test]
+    fn test_keys() {
+        for name in &[
+            "a", "Up", "Enter", "Space", "Hash", "Ctrl-x", "F1", "F12", "F", "PageDown",
+        ] {
+            let key = parse_key(name).unwrap();
+            assert_eq!(key_name(key), *name);
+        }
+        assert_eq!(parse_key("F13"), Err("unknown key 'F13'".to_string()));
+        assert_eq!(parse_key("Ctrl-"), Err("unknown key 'Ctrl-'".to_string()));
+    }
+
+    #[test]
+    fn test_help() {
+        let keys = KeyBindings::parse("Quit q Esc # comment\n\nDeltaNorth k Up\n").unwrap();
+        assert_eq!(
+            keys.help_lines(),
+            vec![
+                "Move north              Up k".to_string(),
+                "Save and quit           Esc q".to_string(),
+            ]
+        );
+
+        let keys = KeyBindings::parse("Quit q\nLook Hash #x\n").unwrap();
+        assert_eq!(keys.action(Key::Char('#')), Some(PlayerAction::Look));
+        assert_eq!(keys.action(Key::Char('x')), None);
+    }
+
+    fn parse_err(text: &str) -> String {
+        KeyBindings::parse(text).err().unwrap()
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_err("Fly f\n"), "1: unknown action 'Fly'");
+        assert_eq!(parse_err("\nQuit Hyper-q\n"), "2: unknown key 'Hyper-q'");
+        assert_eq!(parse_err("Quit\n"), "1: Quit isn't bound to any keys");
+        assert_eq!(
+            parse_err("DeltaNorth k\nQuit q k\n"),
+            "2: 'k' is bound to both DeltaNorth and Quit"
+        );
+        assert_eq!(parse_err("DeltaNorth k\n"), "Quit isn't bound to a key");
+        assert!(KeyBindings::parse("Quit q\nQuit q\n").is_ok());
+    }
+}
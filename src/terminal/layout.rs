@@ -0,0 +1,93 @@
+//! Splits the screen into the map, the status bar, and the message pane. The
+//! layout is recomputed whenever the terminal is resized.
+use super::super::core::*;
+use super::backend::*;
+use super::color::Color;
+use super::console::MESSAGE_LINES;
+use super::status_bar::STATUS_BAR_LINES;
+
+/// Smallest terminal the game will render into. Below this the map would be
+/// too small to play (and the status bar would be truncated).
+pub const MIN_SIZE: Size = Size {
+    width: 40,
+    height: 16,
+};
+
+/// Screen regions. All coordinates are zero based: the map starts at the top
+/// left with the status bar and then the message pane below it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub size: Size,
+
+    /// Size of the map viewport.
+    pub map: Size,
+
+    /// Line the status bar is drawn on.
+    pub status_y: i32,
+
+    /// First line of the message pane.
+    pub messages_y: i32,
+}
+
+impl Layout {
+    pub fn new(terminal_size: Size) -> Layout {
+        let map_height = (terminal_size.height - STATUS_BAR_LINES - MESSAGE_LINES).max(0);
+        Layout {
+            size: terminal_size,
+            map: Size::new(terminal_size.width, map_height),
+            status_y: map_height,
+            messages_y: map_height + STATUS_BAR_LINES,
+        }
+    }
+
+    pub fn is_too_small(&self) -> bool {
+        self.size.width < MIN_SIZE.width || self.size.height < MIN_SIZE.height
+    }
+}
+
+/// Shown instead of the game when the terminal is smaller than MIN_SIZE.
+pub fn render_too_small(backend: &mut dyn Backend, layout: &Layout) {
+    backend.clear();
+    for (i, line) in too_small_lines(layout.size).iter().enumerate() {
+        backend.put_line(i as i32, Color::Orange, Color::Black, line);
+    }
+    backend.flush();
+}
+
+// --- Private Items ----------------------------------------------------------
+fn too_small_lines(size: Size) -> Vec<String> {
+    vec![
+        "The terminal is too small.".to_string(),
+        format!("It is {}x{}.", size.width, size.height),
+        format!(
+            "It must be at least {}x{}.",
+            MIN_SIZE.width, MIN_SIZE.height
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let layout = Layout::new(Size::new(80, 24));
+        assert_eq!(layout.map, Size::new(80, 18));
+        assert_eq!(layout.status_y, 18);
+        assert_eq!(layout.messages_y, 19);
+        assert_eq!(layout.messages_y + MESSAGE_LINES, 24);
+        assert!(!layout.is_too_small());
+    }
+
+    #[test]
+    fn test_too_small() {
+        assert!(!Layout::new(MIN_SIZE).is_too_small());
+        assert!(Layout::new(Size::new(MIN_SIZE.width - 1, 50)).is_too_small());
+
+        let layout = Layout::new(Size::new(100, 3));
+        assert!(layout.is_too_small());
+        assert_eq!(layout.map.height, 0, "regions never have negative sizes");
+        assert_eq!(too_small_lines(layout.size)[1], "It is 100x3.");
+    }
+}
@@ -0,0 +1,179 @@
+//! Look mode lets the player move a cursor over the map to examine what is (or
+//! was) there.
+use super::super::core::*;
+use super::super::player::*;
+use super::super::species::*;
+use super::keys::*;
+use termion::event::Key;
+
+/// Location of the look cursor in screen coordinates. This is constrained to
+/// the map viewport.
+pub struct LookCursor {
+    pub loc: Point,
+    size: Size,
+}
+
+impl LookCursor {
+    pub fn new(loc: Point, size: Size) -> LookCursor {
+        LookCursor { loc, size }
+    }
+
+    /// Returns false if look mode should be exited.
+    pub fn on_key(&mut self, key: Key, keys: &KeyBindings) -> bool {
+        let (dx, dy) = match keys.action(key) {
+            Some(PlayerAction::Look) | Some(PlayerAction::Quit) => return false,
+            Some(action) => action_delta(action).unwrap_or((0, 0)),
+            None if key == Key::Esc => return false,
+            None => (0, 0),
+        };
+        self.loc = Point::new(
+            (self.loc.x + dx).clamp(0, self.size.width - 1),
+            (self.loc.y + dy).clamp(0, self.size.height - 1),
+        );
+        true
+    }
+}
+
+/// Returns a description of what the player knows about a cell, e.g. "You see
+/// an ay on shallow water. A giant wolf that hunts in packs.".
+pub fn describe_cell(store: &Store, species: &SpeciesTable, cell: &Subject) -> String {
+    let terrain = match store.lookup_terrain(cell, Predicate::LastSeenTerrain) {
+        Some(terrain) => terrain_description(terrain),
+        None => return "You haven't seen this location.".to_string(),
+    };
+    let visible = store.lookup_bool(cell, Predicate::Visible).unwrap_or(false);
+    match store.lookup_ref(cell, Predicate::LastSeenChar) {
+        Some(ch) if ch == *PLAYER && visible => format!("You are standing on {}.", terrain),
+        Some(ch) => {
+            let species = species.of(store, &ch);
+            let name = store
+                .lookup_str(&ch, Predicate::Name)
+                .unwrap_or_else(|| format!("{} {}", article(&species.name), species.name));
+            let mut text = if visible {
+                format!("You see {} on {}.", name, terrain)
+            } else {
+                format!("You remember seeing {} on {}.", name, terrain)
+            };
+            if let Some(description) = &species.description {
+                text += " ";
+                text += description;
+            }
+            text
+        }
+        None if visible => format!("You see {}.", terrain),
+        None => format!("You remember seeing {}.", terrain),
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+fn terrain_description(terrain: Terrain) -> &'static str {
+    match terrain {
+        Terrain::DeepWater => "deep water",
+        Terrain::Ground => "the ground",
+        Terrain::ShallowWater => "shallow water",
+        Terrain::Wall => "a wall",
+    }
+}
+
+fn article(name: &str) -> &'static str {
+    match name.chars().next() {
+        Some('a') | Some('e') | Some('i') | Some('o') | Some('u') => "an",
+        _ => "a",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        let keys = test_bindings();
+        let mut cursor = LookCursor::new(Point::new(1, 1), Size::new(3, 2));
+        assert!(cursor.on_key(Key::Char('9'), &keys));
+        assert_eq!(cursor.loc, Point::new(2, 0));
+        assert!(cursor.on_key(Key::Right, &keys));
+        assert!(cursor.on_key(Key::Char('k'), &keys));
+        assert_eq!(cursor.loc, Point::new(2, 0), "cursor stays within the map");
+        assert!(cursor.on_key(Key::Char('b'), &keys));
+        assert_eq!(cursor.loc, Point::new(1, 1));
+        assert!(cursor.on_key(Key::Char('Z'), &keys));
+        assert!(!cursor.on_key(Key::Esc, &keys));
+        assert!(!cursor.on_key(Key::Char('x'), &keys));
+    }
+
+    #[test]
+    fn test_describe_cell() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let mut store = Store::new(&logger);
+        let species = test_species();
+        let event = Event::NewLevel;
+        let ay = Subject::new_unique("ay-1");
+        store.insert(
+            &event,
+            &ay,
+            Predicate::Species,
+            Object::Str("ay".to_string()),
+        );
+        store.insert(
+            &event,
+            &PLAYER,
+            Predicate::Species,
+            Object::Str("human".to_string()),
+        );
+
+        let unseen = cell(Point::new(0, 0));
+        assert_eq!(
+            describe_cell(&store, &species, &unseen),
+            "You haven't seen this location."
+        );
+
+        let wall = cell(Point::new(1, 0));
+        store.insert(
+            &event,
+            &wall,
+            Predicate::LastSeenTerrain,
+            Object::Terrain(Terrain::Wall),
+        );
+        store.insert(&event, &wall, Predicate::Visible, Object::Bool(true));
+        assert_eq!(describe_cell(&store, &species, &wall), "You see a wall.");
+        store.insert(&event, &wall, Predicate::Visible, Object::Bool(false));
+        assert_eq!(
+            describe_cell(&store, &species, &wall),
+            "You remember seeing a wall."
+        );
+
+        let water = cell(Point::new(2, 0));
+        store.insert(
+            &event,
+            &water,
+            Predicate::LastSeenTerrain,
+            Object::Terrain(Terrain::ShallowWater),
+        );
+        store.insert(&event, &water, Predicate::LastSeenChar, Object::Ref(ay));
+        store.insert(&event, &water, Predicate::Visible, Object::Bool(true));
+        assert_eq!(
+            describe_cell(&store, &species, &water),
+            "You see an ay on shallow water. A giant wolf that hunts in packs."
+        );
+
+        let ground = cell(Point::new(3, 0));
+        store.insert(
+            &event,
+            &ground,
+            Predicate::LastSeenTerrain,
+            Object::Terrain(Terrain::Ground),
+        );
+        store.insert(
+            &event,
+            &ground,
+            Predicate::LastSeenChar,
+            Object::Ref(PLAYER.clone()),
+        );
+        store.insert(&event, &ground, Predicate::Visible, Object::Bool(true));
+        assert_eq!(
+            describe_cell(&store, &species, &ground),
+            "You are standing on the ground."
+        );
+    }
+}
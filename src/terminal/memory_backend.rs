@@ -0,0 +1,108 @@
+//! Backend that draws into an in-memory grid and reads scripted input. Clones
+//! share the same grid and keys so that one can be handed to the Terminal and
+//! another used to check what was drawn.
+use super::super::core::*;
+use super::backend::*;
+use super::input::*;
+use super::view::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use termion::event::Key;
+
+#[derive(Clone)]
+pub struct MemoryBackend {
+    state: Rc<RefCell<State>>,
+}
+
+impl MemoryBackend {
+    pub fn new(size: Size) -> MemoryBackend {
+        let state = State {
+            size,
+            cells: vec![View::blank(); (size.width * size.height) as usize],
+            cursor: None,
+            input: ScriptedInput::default(),
+        };
+        MemoryBackend {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Queues up keys to be returned by read_input.
+    pub fn push_keys(&self, keys: &[Key]) {
+        self.state.borrow_mut().input.extend(keys.iter().copied());
+    }
+
+    /// Queues up the keys from a script, see ScriptedInput::parse.
+    pub fn push_script(&self, script: &str) -> Result<(), String> {
+        let mut keys = ScriptedInput::parse(script)?;
+        let mut state = self.state.borrow_mut();
+        while let Some(input) = keys.next_input() {
+            state.input.push(input);
+        }
+        Ok(())
+    }
+
+    /// Changes the screen size (which blanks the screen) and queues up a
+    /// resize.
+    pub fn resize(&self, size: Size) {
+        let mut state = self.state.borrow_mut();
+        state.size = size;
+        state.cells = vec![View::blank(); (size.width * size.height) as usize];
+        state.input.push(Input::Resize);
+    }
+
+    pub fn cell(&self, loc: Point) -> View {
+        let state = self.state.borrow();
+        state.cells[(loc.x + loc.y * state.size.width) as usize]
+    }
+
+    pub fn cursor(&self) -> Option<Point> {
+        self.state.borrow().cursor
+    }
+
+    /// Returns the symbols on each line of the screen with trailing spaces
+    /// removed.
+    pub fn lines(&self) -> Vec<String> {
+        let state = self.state.borrow();
+        state
+            .cells
+            .chunks(state.size.width as usize)
+            .map(|line| {
+                let text: String = line.iter().map(|view| view.symbol).collect();
+                text.trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn size(&self) -> Size {
+        self.state.borrow().size
+    }
+
+    fn put_cell(&mut self, loc: Point, view: View) {
+        let mut state = self.state.borrow_mut();
+        let size = state.size;
+        if loc.x >= 0 && loc.x < size.width && loc.y >= 0 && loc.y < size.height {
+            state.cells[(loc.x + loc.y * size.width) as usize] = view;
+        }
+    }
+
+    fn set_cursor(&mut self, loc: Option<Point>) {
+        self.state.borrow_mut().cursor = loc;
+    }
+
+    fn flush(&mut self) {}
+
+    fn read_input(&mut self) -> Option<Input> {
+        self.state.borrow_mut().input.next_input()
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+struct State {
+    size: Size,
+    cells: Vec<View>,
+    cursor: Option<Point>,
+    input: ScriptedInput,
+}
@@ -1,41 +1,135 @@
-mod color;
+mod backend;
+pub mod color;
+mod console;
+mod frame;
+mod input;
+mod keys;
+mod layout;
+mod look;
+#[cfg(test)]
+mod memory_backend;
+mod prompt;
 mod render;
+mod status_bar;
+mod termion_backend;
+mod title;
 mod view;
 
 use super::core::*;
 use super::level::*;
 use super::player::*;
+use super::species::*;
+use backend::*;
+use console::*;
+use input::*;
+pub use input::{InputReader, InputSource, ScriptedInput};
+pub use keys::KeyBindings;
+use keys::*;
+use layout::*;
+use look::*;
+#[cfg(test)]
+pub use memory_backend::MemoryBackend;
 use render::*;
 use slog::Logger;
-use std::io::Write;
-use std::panic::{set_hook, take_hook};
-use std::process;
-use termion::input::TermRead;
-use termion::raw::IntoRawMode;
-
-type RawTerminal = termion::raw::RawTerminal<std::io::Stdout>;
+use status_bar::*;
+use std::rc::Rc;
+use termion::event::Key;
+use termion_backend::*;
+pub use title::{Character, MenuChoice, MenuOptions};
 
 pub enum TerminalEventResult {
     Running,
     NotRunning,
+
+    /// The user wants to save the game (and keep playing).
+    SaveGame,
 }
 
 enum TerminalActionResult {
+    Help,
+    Look,
     NotRunning,
+    SaveGame,
+    ShowMessages,
     Ignored,
 }
 
 pub struct Terminal {
     logger: Logger,
-    stdout: RawTerminal,
+    backend: Option<Box<dyn Backend>>, // None when replaying
+    status: Option<String>,            // shown on the top line until the next key press
+    species: Option<Rc<SpeciesTable>>, // None when replaying
+    keys: Option<KeyBindings>,         // None when replaying
+    layout: Layout,
 }
 
+// Screen size used when there is no terminal to query.
+const HEADLESS_SIZE: Size = Size {
+    width: 80,
+    height: 24,
+};
+
 impl Terminal {
-    pub fn new(root_logger: &Logger) -> Terminal {
+    /// Input normally comes from an InputReader but it can also be scripted.
+    pub fn new(
+        root_logger: &Logger,
+        species: Rc<SpeciesTable>,
+        keys: KeyBindings,
+        input: Box<dyn InputSource>,
+    ) -> Terminal {
+        let backend = Box::new(TermionBackend::new(input));
+        Terminal::with_backend(root_logger, species, keys, backend)
+    }
+
+    pub fn with_backend(
+        root_logger: &Logger,
+        species: Rc<SpeciesTable>,
+        keys: KeyBindings,
+        backend: Box<dyn Backend>,
+    ) -> Terminal {
         Terminal {
             logger: root_logger.new(o!()),
-            stdout: setup_terminal(),
+            layout: Layout::new(backend.size()),
+            backend: Some(backend),
+            status: None,
+            species: Some(species),
+            keys: Some(keys),
+        }
+    }
+
+    /// Used when replaying games: the store is updated as usual but nothing is
+    /// rendered and input is never read (the player's actions come from the
+    /// replayed events).
+    pub fn new_headless(root_logger: &Logger) -> Terminal {
+        Terminal {
+            logger: root_logger.new(o!()),
+            backend: None,
+            status: None,
+            species: None,
+            keys: None,
+            layout: Layout::new(HEADLESS_SIZE),
+        }
+    }
+
+    /// Shows the title screen (and character creation if the player starts a
+    /// new game). If the player quits the terminal is restored.
+    pub fn main_menu(&mut self, options: &MenuOptions, error: Option<&str>) -> MenuChoice {
+        let backend = self.backend.as_deref_mut().unwrap();
+        let species = self.species.as_ref().unwrap();
+        let choice = title::main_menu(backend, species, options, error);
+        if let MenuChoice::Quit = choice {
+            backend.restore();
+        } else {
+            backend.clear();
+            backend.flush();
         }
+        choice
+    }
+
+    /// Displays a line of text to the user, e.g. "Saved game.".
+    pub fn set_status(&mut self, text: &str) {
+        info!(self.logger, "status"; "text" => text);
+        self.status = Some(text.to_string());
     }
 
     pub fn on_event(
@@ -46,137 +140,369 @@ impl Terminal {
     ) -> TerminalEventResult {
         let mut result = TerminalEventResult::Running;
         if let Event::AdvanceTime(time) = event {
+            if player_is_dead(store) {
+                self.do_game_over();
+                return TerminalEventResult::NotRunning;
+            }
+
             let ready = player_ready_time(store);
             assert!(*time <= ready);
             if *time == ready {
-                let (width, height) = termion::terminal_size().expect("couldn't get terminal size");
-                let terminal_size = Size::new(i32::from(width), i32::from(height));
-                let seen = get_last_seen(store, event, terminal_size);
+                if self.backend.is_some() {
+                    // We may have missed a resize while a modal screen was up.
+                    self.do_update_layout();
+                    let seen = get_last_seen(store, event, self.layout.map);
 
-                // TODO: On replay need to skip these two (may want a flag to enable them).
-                self.do_render_screen(store, terminal_size, &seen);
-                result = self.do_handle_input(event, pending, store, ready);
+                    self.do_render_screen(store, &seen);
+                    result = self.do_handle_input(pending, store, ready, seen);
+                } else {
+                    // We still need to do this so that the store winds up with
+                    // the same state as the original game.
+                    let _ = get_last_seen(store, event, self.layout.map);
+                }
             }
         }
         result
     }
 
-    fn do_render_screen(
-        &mut self,
-        store: &mut Store,
-        terminal_size: Size,
-        seen: &Vec<(Point, Subject)>,
-    ) {
-        render_level(&mut self.stdout, store, terminal_size, seen);
-        self.stdout.flush().unwrap();
+    /// Recomputes the layout using the current screen size.
+    fn do_update_layout(&mut self) {
+        let layout = Layout::new(self.backend.as_ref().unwrap().size());
+        if layout != self.layout {
+            debug!(self.logger, "resized"; "size" => ?layout.size);
+            self.layout = layout;
+        }
+    }
+
+    fn do_render_screen(&mut self, store: &Store, seen: &[(Point, Subject)]) {
+        let backend = self.backend.as_deref_mut().unwrap();
+        if self.layout.is_too_small() {
+            render_too_small(backend, &self.layout);
+            return;
+        }
+
+        let species = self.species.as_ref().unwrap();
+        backend.clear();
+        render_level(backend, store, species, seen, self.layout.map);
+        render_status_bar(backend, store, species, self.layout.status_y);
+        render_messages(backend, store, &self.layout);
+        if let Some(text) = &self.status {
+            render_status(backend, text);
+        }
+        backend.flush();
+    }
+
+    fn do_look(&mut self, store: &Store, seen: &[(Point, Subject)]) {
+        let backend = self.backend.as_deref_mut().unwrap();
+        let species = self.species.as_ref().unwrap();
+        let keys = self.keys.as_ref().unwrap();
+
+        // The cursor starts out on the player.
+        let player_cell = cell(player_loc(store));
+        let size = self.layout.map;
+        let start = seen
+            .iter()
+            .find(|(_, subject)| *subject == player_cell)
+            .map_or(Point::new(size.width / 2, size.height / 2), |(loc, _)| *loc);
+        let mut cursor = LookCursor::new(start, size);
+        loop {
+            if let Some((_, subject)) = seen.iter().find(|(loc, _)| *loc == cursor.loc) {
+                render_status(backend, &describe_cell(store, species, subject));
+            }
+            backend.set_cursor(Some(cursor.loc));
+            backend.flush();
+
+            // Resizing exits look mode because the cursor may no longer be
+            // over the map.
+            match backend.read_input() {
+                Some(Input::Key(key)) if cursor.on_key(key, keys) => (),
+                _ => break,
+            }
+        }
+
+        // The map (and the status line) will be redrawn by the next AdvanceTime event.
+        backend.set_cursor(None);
+        backend.flush();
+    }
+
+    fn do_help(&mut self) {
+        let backend = self.backend.as_deref_mut().unwrap();
+        let keys = self.keys.as_ref().unwrap();
+        loop {
+            render_help(backend, keys);
+            if let Some(Input::Key(_)) | None = backend.read_input() {
+                break;
+            }
+        }
+        // The map will be redrawn by the next AdvanceTime event.
+    }
+
+    fn do_show_messages(&mut self, store: &Store) {
+        let backend = self.backend.as_deref_mut().unwrap();
+        let keys = self.keys.as_ref().unwrap();
+        let mut scrollback = Scrollback::new(store, backend.size());
+        scrollback.render(backend);
+        while let Some(input) = backend.read_input() {
+            match input {
+                Input::Key(key) if !scrollback.on_key(key, keys) => break,
+                Input::Key(_) => (),
+                Input::Resize => {
+                    // The messages have to be re-wrapped for the new width.
+                    scrollback = Scrollback::new(store, backend.size());
+                }
+            }
+            scrollback.render(backend);
+        }
+        // The map will be redrawn by the next AdvanceTime event.
+    }
+
+    fn do_game_over(&mut self) {
+        if let Some(backend) = self.backend.as_deref_mut() {
+            render_status(backend, "You have died. Press any key to exit.");
+            backend.flush();
+            let _ = backend.read_key();
+            backend.restore();
+        }
     }
 
+    /// Waits for a key press, redrawing the screen if the terminal is resized
+    /// in the meantime. If there is no more input the game stops.
     fn do_handle_input(
         &mut self,
-        event: &Event,
         pending: &mut PendingEvents,
-        store: &mut Store,
+        store: &Store,
         ready: Time,
+        mut seen: Vec<(Point, Subject)>,
     ) -> TerminalEventResult {
-        let stdin = std::io::stdin();
-        let mut key_iter = stdin.keys(); // TODO: may want to make this a field
-        if let Some(c) = key_iter.next() {
-            let cc = c.unwrap();
-            debug!(self.logger, "handling"; "key" => ?cc);
-            if let Some(action) = key_to_action(cc) {
-                match on_player_action(store, pending, action) {
-                    PlayerActionResult::Acted(duration) => store.insert(
-                        event,
-                        &PLAYER,
-                        Predicate::Ready,
-                        Object::Time(ready + duration),
-                    ),
-                    PlayerActionResult::Error => {
-                        let _ = write!(self.stdout, "\x07");
+        while let Some(input) = self.backend.as_mut().unwrap().read_input() {
+            match input {
+                Input::Resize => {
+                    self.do_update_layout();
+                    seen = screen_tiles(store, self.layout.map);
+                    self.do_render_screen(store, &seen);
+                }
+                Input::Key(key) => {
+                    // Only allow quitting until the terminal is large enough to play.
+                    let quit = self.keys.as_ref().unwrap().action(key) == Some(PlayerAction::Quit);
+                    if !self.layout.is_too_small() || quit {
+                        return self.do_handle_key(pending, store, ready, &seen, key);
                     }
-                    PlayerActionResult::Ignored => match on_game_action(action) {
-                        TerminalActionResult::NotRunning => {
-                            restore_terminal();
-                            return TerminalEventResult::NotRunning;
-                        }
-                        TerminalActionResult::Ignored => {
-                            panic!("Didn't handle action {:?}", action)
-                        }
-                    },
                 }
-            // }
-            // } else if let Some(action) = map_game_action(cc) {
-            // 	dispatch_game_action(&mut self.stdout, &mut game, action);
-            // 	if !game.running() {
-            // 		break;
-            // 	}
-            // } else {
-            // 	warn!(self.logger, "user pressed"; "key" => format!("{:?}", cc));
-            // 	let _ = write!(self.stdout, "\x07");
-            // 	self.stdout.flush().unwrap();
-            } else {
-                // Note that we don't advance ready time for bad keys (or
-                // game actions). This means that the event loop will queue
-                // up another AdvanceTime event for the same time but that
-                // doesn't do any harm and simplifies our logic.
-                trace!(self.logger, "ignoring"; "key" => ?cc);
             }
         }
+
+        // Stdin was closed or a script ran out.
+        info!(self.logger, "out of input");
+        self.backend.as_mut().unwrap().restore();
+        TerminalEventResult::NotRunning
+    }
+
+    fn do_handle_key(
+        &mut self,
+        pending: &mut PendingEvents,
+        store: &Store,
+        ready: Time,
+        seen: &[(Point, Subject)],
+        cc: Key,
+    ) -> TerminalEventResult {
+        debug!(self.logger, "handling"; "key" => ?cc);
+        self.status = None;
+        if let Some(action) = self.keys.as_ref().unwrap().action(cc) {
+            let species = self.species.as_ref().unwrap();
+            match on_player_action(store, species, pending, action) {
+                PlayerActionResult::Acted(duration) => {
+                    pending.push_back(Event::SetPlayerReady(ready + duration))
+                }
+                PlayerActionResult::Error(reason) => {
+                    pending.push_back(Event::AddMessage(Topic::Error, reason))
+                }
+                PlayerActionResult::Ignored => match on_game_action(action) {
+                    TerminalActionResult::NotRunning => {
+                        self.backend.as_mut().unwrap().restore();
+                        return TerminalEventResult::NotRunning;
+                    }
+                    TerminalActionResult::SaveGame => {
+                        return TerminalEventResult::SaveGame;
+                    }
+                    TerminalActionResult::Help => self.do_help(),
+                    TerminalActionResult::Look => self.do_look(store, seen),
+                    TerminalActionResult::ShowMessages => self.do_show_messages(store),
+                    TerminalActionResult::Ignored => {
+                        panic!("Didn't handle action {:?}", action)
+                    }
+                },
+            }
+        } else {
+            // Note that we don't advance ready time for bad keys (or
+            // game actions). This means that the event loop will queue
+            // up another AdvanceTime event for the same time but that
+            // doesn't do any harm and simplifies our logic.
+            trace!(self.logger, "ignoring"; "key" => ?cc);
+            let keys = self.keys.as_ref().unwrap();
+            let mut text = "That key isn't bound to anything.".to_string();
+            if let Some(help) = keys.key_for(PlayerAction::Help) {
+                text += &format!(" Press {} for help.", key_name(help));
+            }
+            pending.push_back(Event::AddMessage(Topic::Error, text));
+        }
         TerminalEventResult::Running
     }
 }
 
 fn on_game_action(action: PlayerAction) -> TerminalActionResult {
-    if let PlayerAction::Quit = action {
-        TerminalActionResult::NotRunning
-    } else {
-        TerminalActionResult::Ignored
+    match action {
+        PlayerAction::Help => TerminalActionResult::Help,
+        PlayerAction::Look => TerminalActionResult::Look,
+        PlayerAction::Quit => TerminalActionResult::NotRunning,
+        PlayerAction::SaveGame => TerminalActionResult::SaveGame,
+        PlayerAction::ShowMessages => TerminalActionResult::ShowMessages,
+        _ => TerminalActionResult::Ignored,
     }
 }
 
-fn setup_terminal() -> RawTerminal {
-    let mut stdout = std::io::stdout().into_raw_mode().unwrap();
-    let _ = write!(stdout, "{}{}", termion::cursor::Hide, termion::clear::All);
-    stdout.flush().unwrap();
-    let old_hook = take_hook();
-    set_hook(Box::new(move |arg| {
-        restore_terminal();
-        old_hook(arg);
-    }));
-    stdout
-}
+#[cfg(test)]
+mod tests {
+    use super::super::messages::*;
+    use super::super::npc::*;
+    use super::super::service::*;
+    use super::color::Color;
+    use super::memory_backend::*;
+    use super::view::*;
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
 
-fn restore_terminal() {
-    let mut stdout = std::io::stdout();
-    let _ = write!(
-        stdout,
-        "{}{}{}{}",
-        termion::style::Reset,
-        termion::cursor::Restore,
-        termion::cursor::Show,
-        termion::cursor::Goto(1, 1)
-    );
-    let _ = write!(stdout, "{}", termion::clear::All);
-    stdout.flush().unwrap();
-
-    let _ = process::Command::new("reset").output(); // new line mode isn't reset w/o this
-}
+    // A small walled cave with the player, an ay, and a pool of water.
+    fn new_store(logger: &Logger, species: &Rc<SpeciesTable>) -> Store {
+        let mut store = Store::new(logger);
+        let mut services = Services::new();
+        services.register(Box::new(LevelService));
+        services.register(Box::new(MessageService));
+        services.register(Box::new(PlayerService::new(species.clone())));
+        services.register(Box::new(NPCService::new(species.clone())));
+
+        let mut events = vec![
+            Event::NewGame(1),
+            Event::NewCharacter(
+                "Kalam".to_string(),
+                "human".to_string(),
+                "Marine".to_string(),
+            ),
+            Event::ResetLevel("Cave".to_string(), Size::new(7, 4), Terrain::Wall),
+        ];
+        for y in 1..3 {
+            for x in 1..6 {
+                events.push(Event::SetTerrain(Point::new(x, y), Terrain::Ground));
+            }
+        }
+        events.push(Event::SetTerrain(Point::new(5, 2), Terrain::ShallowWater));
+        events.push(Event::SetPlayer(Point::new(1, 1)));
+        events.push(Event::NewNPC(
+            Point::new(3, 2),
+            "ay".to_string(),
+            20,
+            Time::from_secs(2.0),
+        ));
+        events.push(Event::AddMessage(
+            Topic::Warning,
+            "You hear howling.".to_string(),
+        ));
+        events.push(ready_event());
+
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut pending = PendingEvents::new();
+        for event in events.iter() {
+            services.on_event(&mut store, &mut rng, event, &mut pending);
+        }
+        store
+    }
+
+    // The player is initially ready to act at this time.
+    fn ready_event() -> Event {
+        Event::AdvanceTime(Time::from_secs(1.0))
+    }
+
+    fn render(backend: &MemoryBackend, keys: &[Key]) -> Terminal {
+        let logger = Logger::root(slog::Discard, o!());
+        let species = test_species();
+        let mut store = new_store(&logger, &species);
+        let mut terminal =
+            Terminal::with_backend(&logger, species, test_bindings(), Box::new(backend.clone()));
+        backend.push_keys(keys);
+        let mut pending = PendingEvents::new();
+        terminal.on_event(&ready_event(), &mut pending, &mut store);
+        terminal
+    }
+
+    #[test]
+    fn test_screen() {
+        let backend = MemoryBackend::new(MIN_SIZE);
+        render(&backend, &[]);
+
+        let mut expected = vec![String::new(); 16];
+        expected[4] = "                   #######".to_string();
+        expected[5] = "                   #@    #".to_string();
+        expected[6] = "                   #  a ~#".to_string();
+        expected[7] = "                   #######".to_string();
+        expected[10] = "Kalam  Cave  1.0s  (1, 1)  HP 90/90".to_string();
+        expected[15] = "You hear howling.".to_string();
+        assert_eq!(backend.lines(), expected);
+    }
+
+    #[test]
+    fn test_views() {
+        let backend = MemoryBackend::new(MIN_SIZE);
+        render(&backend, &[]);
+
+        // The level is drawn with its top left corner at (19, 4).
+        let species = test_species();
+        let view = |x, y| backend.cell(Point::new(19 + x, 4 + y));
+        assert_eq!(
+            view(1, 1),
+            View {
+                symbol: '@',
+                fg: species.get("human").unwrap().color,
+                bg: Color::Black
+            }
+        );
+        assert_eq!(view(3, 2).fg, species.get("ay").unwrap().color);
+        assert_eq!(view(5, 2).bg, Color::LightBlue, "shallow water");
+        assert_eq!(view(0, 0).symbol, '#');
+        assert_eq!(view(-1, 0).bg, Color::LightGrey, "outside the level");
+        assert_eq!(backend.cell(Point::new(0, 10)).bg, Color::DarkSlateGray);
+    }
+
+    #[test]
+    fn test_look() {
+        let backend = MemoryBackend::new(Size::new(80, 24));
+        render(&backend, &[Key::Char('x')]);
+        assert_eq!(backend.lines()[0], "You are standing on the ground.");
+
+        render(&backend, &[Key::Char('x'), Key::Char('n'), Key::Char('l')]);
+        assert_eq!(
+            backend.lines()[0],
+            "You see an ay on the ground. A giant wolf that hunts in packs."
+        );
+        assert_eq!(backend.cursor(), None, "cursor is hidden after look mode");
+    }
+
+    #[test]
+    fn test_help() {
+        let backend = MemoryBackend::new(MIN_SIZE);
+        render(&backend, &[Key::Char('?')]);
+        let lines = backend.lines();
+        assert!(lines[0].starts_with("Move north"));
+        assert_eq!(lines[15], "Press any key to continue.");
+    }
 
-fn key_to_action(key: termion::event::Key) -> Option<PlayerAction> {
-    match key {
-        termion::event::Key::Left => Some(PlayerAction::DeltaWest),
-        termion::event::Key::Right => Some(PlayerAction::DeltaEast),
-        termion::event::Key::Up => Some(PlayerAction::DeltaNorth),
-        termion::event::Key::Down => Some(PlayerAction::DeltaSouth),
-        termion::event::Key::Char('1') => Some(PlayerAction::DeltaSouthWest),
-        termion::event::Key::Char('2') => Some(PlayerAction::DeltaSouth),
-        termion::event::Key::Char('3') => Some(PlayerAction::DeltaSouthEast),
-        termion::event::Key::Char('4') => Some(PlayerAction::DeltaWest),
-        termion::event::Key::Char('6') => Some(PlayerAction::DeltaEast),
-        termion::event::Key::Char('7') => Some(PlayerAction::DeltaNorthWest),
-        termion::event::Key::Char('8') => Some(PlayerAction::DeltaNorth),
-        termion::event::Key::Char('9') => Some(PlayerAction::DeltaNorthEast),
-        termion::event::Key::Char('q') => Some(PlayerAction::Quit),
-        _ => None,
+    #[test]
+    fn test_too_small() {
+        let backend = MemoryBackend::new(MIN_SIZE);
+        backend.resize(Size::new(30, 10));
+        render(&backend, &[Key::Char('l')]);
+        let lines = backend.lines();
+        assert_eq!(lines[0], "The terminal is too small.");
+        assert_eq!(lines[1], "It is 30x10.");
+        assert_eq!(lines.len(), 10);
     }
 }
@@ -0,0 +1,190 @@
+//! Full screen prompts, e.g. for the title screen and character creation.
+use super::super::core::*;
+use super::backend::*;
+use super::color::Color;
+use super::input::*;
+use super::keys::key_name;
+use termion::event::Key;
+
+pub struct Choice {
+    key: Key,
+    text: String,
+}
+
+impl Choice {
+    pub fn new(key: Key, text: &str) -> Choice {
+        Choice {
+            key,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// Asks the user to select from one of several choices. Returns the index of
+/// the chosen choice or None if the user pressed escape.
+pub fn prompt(backend: &mut dyn Backend, header: &[String], choices: &[Choice]) -> Option<usize> {
+    loop {
+        render_header(backend, header);
+        render_choices(backend, header.len() + 1, choices);
+        backend.flush();
+
+        // Keep going until a choice is made (or we're resized and need to redraw).
+        loop {
+            match backend.read_input() {
+                Some(Input::Key(Key::Esc)) | None => return None,
+                Some(Input::Key(key)) => {
+                    if let Some(index) = match_choice(key, choices) {
+                        return Some(index);
+                    }
+                }
+                Some(Input::Resize) => break,
+            }
+        }
+    }
+}
+
+/// Asks the user to type in a line of text (which cannot be empty). Returns
+/// None if the user pressed escape.
+pub fn prompt_text(
+    backend: &mut dyn Backend,
+    header: &[String],
+    question: &str,
+    max_len: usize,
+) -> Option<String> {
+    let mut text = String::new();
+    let y = (header.len() + 1) as i32;
+    loop {
+        render_header(backend, header);
+        backend.put_str(Point::new(0, y), Color::LightBlue, Color::Black, question);
+        let x = question.chars().count() as i32;
+        backend.put_str(Point::new(x, y), Color::White, Color::Black, &text);
+        let x = x + text.chars().count() as i32;
+        backend.set_cursor(Some(Point::new(x, y)));
+        backend.flush();
+
+        let key = match backend.read_input() {
+            Some(Input::Key(key)) => key,
+            Some(Input::Resize) => continue,
+            None => Key::Esc,
+        };
+        let edit = edit_text(&mut text, key, max_len);
+        if edit != TextEdit::Editing {
+            backend.set_cursor(None);
+            backend.flush();
+            return if edit == TextEdit::Done {
+                Some(text)
+            } else {
+                None
+            };
+        }
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+#[derive(Debug, PartialEq)]
+enum TextEdit {
+    Editing,
+    Done,
+    Cancelled,
+}
+
+fn edit_text(text: &mut String, key: Key, max_len: usize) -> TextEdit {
+    match key {
+        Key::Esc => return TextEdit::Cancelled,
+        Key::Char('\n') if !text.trim().is_empty() => {
+            *text = text.trim().to_string();
+            return TextEdit::Done;
+        }
+        Key::Backspace => {
+            text.pop();
+        }
+        // Quotes would break the text event format.
+        Key::Char(c) if !c.is_control() && c != '"' && text.chars().count() < max_len => {
+            text.push(c)
+        }
+        _ => (),
+    }
+    TextEdit::Editing
+}
+
+fn match_choice(key: Key, choices: &[Choice]) -> Option<usize> {
+    choices.iter().position(|choice| choice.key == key)
+}
+
+fn render_header(backend: &mut dyn Backend, header: &[String]) {
+    backend.clear();
+    for (i, line) in header.iter().enumerate() {
+        backend.put_line(i as i32, Color::White, Color::Black, line);
+    }
+}
+
+fn render_choices(backend: &mut dyn Backend, start_y: usize, choices: &[Choice]) {
+    for (i, choice) in choices.iter().enumerate() {
+        let loc = Point::new(0, (start_y + i) as i32);
+        let name = key_name(choice.key) + ": ";
+        backend.put_str(loc, Color::White, Color::Black, &name);
+        let loc = Point::new(name.chars().count() as i32, loc.y);
+        backend.put_str(loc, Color::LightBlue, Color::Black, &choice.text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory_backend::*;
+    use super::*;
+
+    #[test]
+    fn test_match_choice() {
+        let choices = vec![
+            Choice::new(Key::Char('n'), "New game"),
+            Choice::new(Key::Char('q'), "Quit"),
+        ];
+        assert_eq!(match_choice(Key::Char('q'), &choices), Some(1));
+        assert_eq!(match_choice(Key::Char('x'), &choices), None);
+    }
+
+    #[test]
+    fn test_edit_text() {
+        let mut text = String::new();
+        assert_eq!(edit_text(&mut text, Key::Char('\n'), 4), TextEdit::Editing);
+        for c in " Kal\"am".chars() {
+            assert_eq!(edit_text(&mut text, Key::Char(c), 4), TextEdit::Editing);
+        }
+        assert_eq!(
+            text, " Kal",
+            "quotes and characters past max_len are dropped"
+        );
+        edit_text(&mut text, Key::Backspace, 4);
+        edit_text(&mut text, Key::Char('l'), 4);
+        assert_eq!(edit_text(&mut text, Key::Char('\n'), 4), TextEdit::Done);
+        assert_eq!(text, "Kal");
+        assert_eq!(edit_text(&mut text, Key::Esc, 4), TextEdit::Cancelled);
+    }
+
+    #[test]
+    fn test_prompts() {
+        let mut backend = MemoryBackend::new(Size::new(20, 6));
+        let header = vec!["Title".to_string()];
+        backend.push_keys(&[Key::Char('K'), Key::Char('a'), Key::Char('\n')]);
+        assert_eq!(
+            prompt_text(&mut backend, &header, "Name: ", 10),
+            Some("Ka".to_string())
+        );
+        assert_eq!(backend.lines()[2], "Name: Ka");
+        assert_eq!(backend.cursor(), None);
+
+        let choices = vec![
+            Choice::new(Key::Char('a'), "Human"),
+            Choice::new(Key::Char('b'), "Trell"),
+        ];
+        backend.push_keys(&[Key::Char('z'), Key::Char('b')]);
+        assert_eq!(prompt(&mut backend, &header, &choices), Some(1));
+        assert_eq!(
+            backend.lines(),
+            vec!["Title", "", "a: Human", "b: Trell", "", ""]
+        );
+
+        backend.push_keys(&[Key::Esc]);
+        assert_eq!(prompt(&mut backend, &header, &choices), None);
+    }
+}
@@ -1,28 +1,25 @@
 use super::super::core::*;
+use super::super::species::*;
 // use super::super::level::*;
+use super::backend::*;
+use super::color::Color;
 use super::view::*;
-use std::io::Write;
-use termion;
-
-type RawTerminal = termion::raw::RawTerminal<std::io::Stdout>;
 
+/// Renders the map into the viewport at the top of the screen.
 pub fn render_level(
-    stdout: &mut RawTerminal,
-    store: &mut Store,
-    terminal_size: Size,
-    seen: &Vec<(Point, Subject)>,
+    backend: &mut dyn Backend,
+    store: &Store,
+    species: &SpeciesTable,
+    seen: &[(Point, Subject)],
+    map_size: Size,
 ) {
     for (loc, cell) in seen.iter() {
-        let view = View::new(store, cell);
-        let x = (loc.x + 1) as u16; // termion is 1-based
-        let y = (loc.y + 1) as u16;
-        let _ = write!(
-            stdout,
-            "{}{}{}{}",
-            termion::cursor::Goto(x, y),
-            termion::color::Bg(view.bg),
-            termion::color::Fg(view.fg),
-            view.symbol
-        );
+        if loc.x < map_size.width && loc.y < map_size.height {
+            backend.put_cell(*loc, View::new(store, species, cell));
+        }
     }
 }
+
+pub fn render_status(backend: &mut dyn Backend, text: &str) {
+    backend.put_line(0, Color::White, Color::Black, text);
+}
@@ -0,0 +1,94 @@
+//! One line summary of the game state drawn between the map and the messages.
+use super::super::combat::*;
+use super::super::core::*;
+use super::super::level::*;
+use super::super::player::*;
+use super::super::species::*;
+use super::backend::*;
+use super::color::Color;
+
+/// Number of screen lines used by the status bar.
+pub const STATUS_BAR_LINES: i32 = 1;
+
+/// Renders the status bar at screen line y (which is zero based).
+pub fn render_status_bar(backend: &mut dyn Backend, store: &Store, species: &SpeciesTable, y: i32) {
+    let text = status_text(store, species);
+    let hps = char_hps(store, &PLAYER);
+    let max_hps = store.lookup_int(&PLAYER, Predicate::MaxHPs).unwrap_or(hps);
+    let fg = if hps * 3 <= max_hps {
+        Color::Red
+    } else {
+        Color::White
+    };
+    backend.put_line(y, fg, Color::DarkSlateGray, &text);
+}
+
+// --- Private Items ----------------------------------------------------------
+fn status_text(store: &Store, species: &SpeciesTable) -> String {
+    let name = store
+        .lookup_str(&LEVEL, Predicate::Name)
+        .unwrap_or_default();
+    let hps = char_hps(store, &PLAYER);
+    let max_hps = store.lookup_int(&PLAYER, Predicate::MaxHPs).unwrap_or(hps);
+    let mut text = String::new();
+    if let Some(player_name) = store.lookup_str(&PLAYER, Predicate::Name) {
+        text += &player_name;
+        text += "  ";
+    }
+    text += &format!(
+        "{}  {}  {}  HP {}/{}",
+        name,
+        current_time(store),
+        player_loc(store),
+        hps,
+        max_hps
+    );
+    for effect in player_effects(store, species) {
+        text += "  ";
+        text += effect;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::service::*;
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_status_text() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let mut store = Store::new(&logger);
+        let species = test_species();
+        let mut services = Services::new();
+        services.register(Box::new(LevelService));
+        services.register(Box::new(PlayerService::new(species.clone())));
+
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut pending = PendingEvents::new();
+        for event in [
+            Event::NewGame(1),
+            Event::ResetLevel("Cave".to_string(), Size::new(4, 4), Terrain::Ground),
+            Event::SetTerrain(Point::new(2, 1), Terrain::ShallowWater),
+            Event::SetPlayer(Point::new(1, 1)),
+            Event::AdvanceTime(Time::from_secs(12.5)),
+        ]
+        .iter()
+        {
+            services.on_event(&mut store, &mut rng, event, &mut pending);
+        }
+        assert_eq!(
+            status_text(&store, &species),
+            "Cave  12.5s  (1, 1)  HP 75/75"
+        );
+
+        let event = Event::SetPlayer(Point::new(2, 1));
+        services.on_event(&mut store, &mut rng, &event, &mut pending);
+        assert_eq!(
+            status_text(&store, &species),
+            "Cave  12.5s  (2, 1)  HP 75/75  Wading"
+        );
+    }
+}
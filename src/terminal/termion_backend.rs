@@ -0,0 +1,127 @@
+//! Backend for a real terminal. Output is buffered in a FrameBuffer so only the
+//! cells that change are sent to the terminal.
+use super::super::core::*;
+use super::backend::*;
+use super::frame::*;
+use super::input::*;
+use super::view::*;
+use std::io::Write;
+use std::panic::{set_hook, take_hook};
+use std::process;
+use termion::raw::IntoRawMode;
+
+type RawTerminal = termion::raw::RawTerminal<std::io::Stdout>;
+
+pub struct TermionBackend {
+    stdout: RawTerminal,
+    input: Box<dyn InputSource>,
+    size: Size,
+    back: Vec<View>, // what the next flush will draw
+    frame: FrameBuffer,
+    cursor: Option<Point>,
+}
+
+impl TermionBackend {
+    pub fn new(input: Box<dyn InputSource>) -> TermionBackend {
+        let size = get_terminal_size();
+        TermionBackend {
+            stdout: setup_terminal(),
+            input,
+            size,
+            back: blank_screen(size),
+            frame: FrameBuffer::new(),
+            cursor: None,
+        }
+    }
+}
+
+impl Backend for TermionBackend {
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn put_cell(&mut self, loc: Point, view: View) {
+        if loc.x >= 0 && loc.x < self.size.width && loc.y >= 0 && loc.y < self.size.height {
+            self.back[(loc.x + loc.y * self.size.width) as usize] = view;
+        }
+    }
+
+    fn set_cursor(&mut self, loc: Option<Point>) {
+        self.cursor = loc;
+    }
+
+    fn flush(&mut self) {
+        self.frame.render(&mut self.stdout, &self.back, self.size);
+        match self.cursor {
+            // termion is 1-based
+            Some(loc) => {
+                let goto = termion::cursor::Goto((loc.x + 1) as u16, (loc.y + 1) as u16);
+                let _ = write!(self.stdout, "{}{}", goto, termion::cursor::Show);
+            }
+            None => {
+                let _ = write!(self.stdout, "{}", termion::cursor::Hide);
+            }
+        }
+        self.stdout.flush().unwrap();
+    }
+
+    fn read_input(&mut self) -> Option<Input> {
+        let input = self.input.next_input();
+        if let Some(Input::Resize) = input {
+            // The terminal may have re-flowed what was on the screen so we
+            // need to redraw everything.
+            self.size = get_terminal_size();
+            self.back = blank_screen(self.size);
+            let _ = write!(
+                self.stdout,
+                "{}{}",
+                termion::style::Reset,
+                termion::clear::All
+            );
+            self.frame.invalidate();
+        }
+        input
+    }
+
+    fn restore(&mut self) {
+        restore_terminal();
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+fn blank_screen(size: Size) -> Vec<View> {
+    vec![View::blank(); (size.width * size.height) as usize]
+}
+
+fn get_terminal_size() -> Size {
+    let (width, height) = termion::terminal_size().expect("couldn't get terminal size");
+    Size::new(i32::from(width), i32::from(height))
+}
+
+fn setup_terminal() -> RawTerminal {
+    let mut stdout = std::io::stdout().into_raw_mode().unwrap();
+    let _ = write!(stdout, "{}{}", termion::cursor::Hide, termion::clear::All);
+    stdout.flush().unwrap();
+    let old_hook = take_hook();
+    set_hook(Box::new(move |arg| {
+        restore_terminal();
+        old_hook(arg);
+    }));
+    stdout
+}
+
+fn restore_terminal() {
+    let mut stdout = std::io::stdout();
+    let _ = write!(
+        stdout,
+        "{}{}{}{}",
+        termion::style::Reset,
+        termion::cursor::Restore,
+        termion::cursor::Show,
+        termion::cursor::Goto(1, 1)
+    );
+    let _ = write!(stdout, "{}", termion::clear::All);
+    stdout.flush().unwrap();
+
+    let _ = process::Command::new("reset").output(); // new line mode isn't reset w/o this
+}
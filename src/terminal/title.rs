@@ -0,0 +1,187 @@
+//! The title screen shown at startup and character creation.
+use super::super::player::*;
+use super::super::species::*;
+use super::backend::*;
+use super::prompt::*;
+use std::path::PathBuf;
+use termion::event::Key;
+
+/// The choices the player made when creating a new character.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Character {
+    pub name: String,
+    pub species: String,
+    pub background: String,
+}
+
+pub enum MenuChoice {
+    NewGame(Character),
+
+    /// Continue the game that didn't exit normally.
+    Recover,
+
+    /// Continue the saved game.
+    Resume,
+
+    /// Continue a game from an events file.
+    Replay(PathBuf),
+    Quit,
+}
+
+/// Which of the optional menu items to offer.
+pub struct MenuOptions {
+    pub can_recover: bool,
+    pub can_resume: bool,
+}
+
+const MAX_NAME_LEN: usize = 20;
+
+pub fn main_menu(
+    backend: &mut dyn Backend,
+    species: &SpeciesTable,
+    options: &MenuOptions,
+    error: Option<&str>,
+) -> MenuChoice {
+    let mut header = title_lines();
+    if let Some(error) = error {
+        header.push(error.to_string());
+    }
+
+    loop {
+        let mut items = vec![(Key::Char('n'), "New game", MenuItem::NewGame)];
+        if options.can_recover {
+            items.push((
+                Key::Char('c'),
+                "Recover the game that didn't exit normally",
+                MenuItem::Recover,
+            ));
+        }
+        if options.can_resume {
+            items.push((Key::Char('r'), "Resume the saved game", MenuItem::Resume));
+        }
+        items.push((Key::Char('p'), "Replay a saved game", MenuItem::Replay));
+        items.push((Key::Char('q'), "Quit", MenuItem::Quit));
+
+        let choices: Vec<Choice> = items
+            .iter()
+            .map(|(key, text, _)| Choice::new(*key, text))
+            .collect();
+        // Escape (or running out of scripted input) quits.
+        let index = match prompt(backend, &header, &choices) {
+            Some(index) => index,
+            None => return MenuChoice::Quit,
+        };
+        match items[index].2 {
+            MenuItem::NewGame => {
+                if let Some(character) = create_character(backend, species) {
+                    return MenuChoice::NewGame(character);
+                }
+            }
+            MenuItem::Recover => return MenuChoice::Recover,
+            MenuItem::Resume => return MenuChoice::Resume,
+            MenuItem::Replay => {
+                if let Some(path) = prompt_text(backend, &header, "Events file: ", 256) {
+                    return MenuChoice::Replay(PathBuf::from(path));
+                }
+            }
+            MenuItem::Quit => return MenuChoice::Quit,
+        }
+    }
+}
+
+// --- Private Items ----------------------------------------------------------
+#[derive(Clone, Copy)]
+enum MenuItem {
+    NewGame,
+    Recover,
+    Resume,
+    Replay,
+    Quit,
+}
+
+fn title_lines() -> Vec<String> {
+    vec![
+        "Crippled God".to_string(),
+        "A rogue-like based on the Malazan Book of the Fallen.".to_string(),
+        String::new(),
+    ]
+}
+
+/// Returns None if the player backed out with escape.
+fn create_character(backend: &mut dyn Backend, species: &SpeciesTable) -> Option<Character> {
+    let mut header = vec!["Character Creation".to_string(), String::new()];
+    let name = prompt_text(backend, &header, "Name: ", MAX_NAME_LEN)?;
+    header.push(format!("Name: {}", name));
+
+    let races = species.playable();
+    let index = prompt(backend, &header, &race_choices(&races))?;
+    let race = races[index];
+    header.push(format!("Race: {}", display_name(&race.name)));
+
+    let index = prompt(backend, &header, &background_choices())?;
+    Some(Character {
+        name,
+        species: race.name.clone(),
+        background: BACKGROUNDS[index].name.to_string(),
+    })
+}
+
+fn race_choices(races: &[&Species]) -> Vec<Choice> {
+    races
+        .iter()
+        .enumerate()
+        .map(|(i, race)| {
+            let text = format!(
+                "{:<12} {} hps. {}",
+                display_name(&race.name),
+                race.max_hps,
+                race.description.as_deref().unwrap_or("")
+            );
+            Choice::new(choice_key(i), &text)
+        })
+        .collect()
+}
+
+fn background_choices() -> Vec<Choice> {
+    BACKGROUNDS
+        .iter()
+        .enumerate()
+        .map(|(i, background)| {
+            let text = format!(
+                "{:<12} {:+} hps, {:+} damage. {}",
+                background.name, background.hps, background.damage, background.description
+            );
+            Choice::new(choice_key(i), &text)
+        })
+        .collect()
+}
+
+/// Choices are labeled a, b, c, etc.
+fn choice_key(index: usize) -> Key {
+    Key::Char((b'a' + index as u8) as char)
+}
+
+/// Species names use dashes instead of spaces, e.g. "tiste-andii".
+fn display_name(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_name() {
+        assert_eq!(display_name("human"), "Human");
+        assert_eq!(display_name("tiste-andii"), "Tiste Andii");
+    }
+}
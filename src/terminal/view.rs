@@ -1,50 +1,56 @@
 use super::super::core::*;
+use super::super::species::*;
 // use super::super::level::*;
 // use super::super::player::*;
 use super::color;
-use termion;
 
 /// Visual representation of terrain, items, and characters on a position within the map.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct View {
 	pub symbol: char,
-	pub fg: termion::color::AnsiValue,
-	pub bg: termion::color::AnsiValue,
+	pub fg: color::Color,
+	pub bg: color::Color,
 	// TODO: might want to add support for styles, see https://docs.rs/termion/1.5.1/termion/style/index.html
 }
 
 impl View {
-	pub fn new(store: &Store, cell: &Subject) -> View {
-		let seen_terrain = store.lookup_terrain(&cell, Predicate::LastSeenTerrain);
-		let seen_char = store.lookup_ref(&cell, Predicate::LastSeenChar);
-		if store.lookup_bool(&cell, Predicate::Visible).unwrap() {
-			let bg = color::to_termion(if let Some(terrain) = seen_terrain {
+	/// An empty black cell, e.g. for parts of the screen with nothing drawn on them.
+	pub fn blank() -> View {
+		View {
+			symbol: ' ',
+			fg: color::Color::White,
+			bg: color::Color::Black,
+		}
+	}
+
+	pub fn new(store: &Store, species: &SpeciesTable, cell: &Subject) -> View {
+		let seen_terrain = store.lookup_terrain(cell, Predicate::LastSeenTerrain);
+		let seen_char = store.lookup_ref(cell, Predicate::LastSeenChar);
+		if store.lookup_bool(cell, Predicate::Visible).unwrap() {
+			let bg = if let Some(terrain) = seen_terrain {
 				terrain.back_color()
 			} else {
 				color::Color::Black
-			});
+			};
 			if let Some(ch) = seen_char {
-				// let symbol = game.get_species(entity).visible_symbol();
-				let symbol = '@';
-				let fg = if ch == *PLAYER {
-					color::to_termion(color::Color::White)
-				} else {
-					color::to_termion(color::Color::Red)
-				};
+				let species = species.of(store, &ch);
+				let symbol = species.symbol;
+				let fg = species.color;
 				View { symbol, fg, bg }
 			} else if let Some(terrain) = seen_terrain {
-				let fg = color::to_termion(terrain.fore_color());
+				let fg = terrain.fore_color();
 				let symbol = terrain.visible_symbol();
 				View { symbol, fg, bg }
 			} else {
-				let fg = color::to_termion(color::Color::Black);
+				let fg = color::Color::Black;
 				let symbol = '?';
 				View { symbol, fg, bg }
 			}
 		} else {
-			let bg = color::to_termion(color::Color::LightGrey);
-			let fg = color::to_termion(color::Color::DarkGray);
-			let symbol = if seen_char.is_some() {
-				'@'
+			let bg = color::Color::LightGrey;
+			let fg = color::Color::DarkGray;
+			let symbol = if let Some(ch) = seen_char {
+				species.of(store, &ch).symbol
 			} else if let Some(terrain) = seen_terrain {
 				terrain.hidden_symbol()
 			} else {
@@ -94,16 +100,6 @@ impl ToForeColor for Terrain {
 	}
 }
 
-// impl ToForeColor for CharName {
-// 	fn fore_color(&self) -> color::Color {
-// 		match self {
-// 			CharName::Ay => color::Color::BurlyWood,
-// 			CharName::Bhederin => color::Color::Chocolate,
-// 			CharName::Human => color::Color::White,
-// 		}
-// 	}
-// }
-
 impl VisibleSymbol for Terrain {
 	fn visible_symbol(&self) -> char {
 		match self {
@@ -115,16 +111,6 @@ impl VisibleSymbol for Terrain {
 	}
 }
 
-// impl VisibleSymbol for Species {
-// 	fn visible_symbol(&self) -> char {
-// 		match self {
-// 			Species::Ay => 'a',
-// 			Species::Bhederin => 'b',
-// 			Species::Human => '@',
-// 		}
-// 	}
-// }
-
 impl HiddenSymbol for Terrain {
 	fn hidden_symbol(&self) -> char {
 		match self {
@@ -135,13 +121,3 @@ impl HiddenSymbol for Terrain {
 		}
 	}
 }
-
-// impl VisibleSymbol for CharName {
-// 	fn visible_symbol(&self) -> char {
-// 		match self {
-// 			CharName::Ay => 'a',
-// 			CharName::Bhederin => 'b',
-// 			CharName::Human => 'h',
-// 		}
-// 	}
-// }